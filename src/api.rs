@@ -0,0 +1,305 @@
+//! A local HTTP API for driving the swarm from scripts. Every request needs
+//! the `[api]` token from the config as `Authorization: Bearer <token>`.
+//!
+//! ### Endpoints
+//!
+//! - `GET /status`: The [`SwarmStatus`] as JSON.
+//! - `POST /commands`: Runs `{"command": "goto to=bot0 10 20"}` exactly like
+//!   a `!` chat command, answering with whether it ran and its replies.
+//! - `GET /tasks`: The queued tasks and what every bot is running.
+//! - `GET /tasks/types`: Every task type with its aliases, arguments, what
+//!   its free text arguments accept and help, for building `POST /commands`
+//!   requests.
+//! - `DELETE /tasks`: Cancels every running task.
+//! - `DELETE /tasks/{bot}`: Cancels the task `bot` is running. 404 if there's
+//!   no such bot, 409 if it's idle.
+//! - `DELETE /queue`: Drops every queued task.
+//! - `DELETE /queue/{index}`: Drops one queued task.
+//! - `GET /events`: A server-sent event stream of [`SwarmEventKind`]s.
+//! - `GET /events/ws`: The same events as JSON text messages over a
+//!   WebSocket.
+//!
+//! Both event streams take an [`EventFilter`] as their query string, e.g.
+//! `/events/ws?bots=bot0&types=task_ended,died`. Since browsers can't set
+//! headers on an `EventSource` or a WebSocket, they also take the token as
+//! `?token=<token>`.
+
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{
+    Json, Router,
+    extract::{
+        Path, Query, Request, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{delete, get, post},
+};
+use azalea::swarm::Swarm;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+use tracing::{error, info};
+
+use crate::{
+    BotState, NotCancelled, SwarmState,
+    bot_task::{self, registry::TaskSpec},
+    cancel_all, cancel_bot,
+    command_controler::{QueuedTask, TaskProgress},
+    commands::{CommandSource, Origin},
+    events::{EventFilter, SwarmEventKind},
+    persistence::{QueuedTaskSnapshot, TaskSnapshot},
+    status::SwarmStatus,
+};
+
+#[derive(Clone)]
+struct Api {
+    swarm: Swarm,
+    state: SwarmState,
+}
+
+/// Starts the API if the config has a token for it. The address is only read
+/// here, but the token is checked against the current config on every request.
+pub fn serve(swarm: Swarm, state: SwarmState) {
+    let (address, enabled) = {
+        let config = state.config.read();
+        (config.api.address.clone(), !config.api.token.is_empty())
+    };
+    if !enabled {
+        return;
+    }
+
+    let api = Api { swarm, state };
+    let router = Router::new()
+        .route("/status", get(status))
+        .route("/commands", post(command))
+        .route("/tasks", get(tasks).delete(cancel_tasks))
+        .route("/tasks/types", get(task_types))
+        .route("/tasks/{bot}", delete(cancel_task))
+        .route("/queue", delete(clear_queue))
+        .route("/queue/{index}", delete(remove_queued))
+        .route("/events", get(events))
+        .route("/events/ws", get(events_ws))
+        .layer(middleware::from_fn_with_state(api.clone(), authorize))
+        .with_state(api);
+
+    tokio::spawn(async move {
+        let listener = match tokio::net::TcpListener::bind(&address).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                error!("Failed to start the HTTP API on {}: {}", address, err);
+                return;
+            }
+        };
+        info!("HTTP API listening on {}", address);
+        if let Err(err) = axum::serve(listener, router).await {
+            error!("HTTP API stopped: {}", err);
+        }
+    });
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+async fn authorize(State(api): State<Api>, request: Request, next: Next) -> Response {
+    let token = api.state.config.read().api.token.clone();
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|value| value.to_string());
+    let provided = if matches!(request.uri().path(), "/events" | "/events/ws") {
+        bearer.or_else(|| {
+            Query::<TokenQuery>::try_from_uri(request.uri())
+                .ok()
+                .and_then(|query| query.0.token)
+        })
+    } else {
+        bearer
+    };
+    let valid = provided.is_some_and(|provided| same_token(provided.as_bytes(), token.as_bytes()));
+    if token.is_empty() || !valid {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    next.run(request).await
+}
+
+/// Compares every byte whatever the first difference, so the time taken
+/// doesn't give away how much of a guess was right.
+fn same_token(provided: &[u8], token: &[u8]) -> bool {
+    provided.len() == token.len()
+        && provided
+            .iter()
+            .zip(token)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+async fn status(State(api): State<Api>) -> Json<SwarmStatus> {
+    Json(SwarmStatus::of(&api.swarm, &api.state))
+}
+
+#[derive(Deserialize)]
+struct CommandRequest {
+    command: String,
+}
+
+#[derive(Serialize)]
+struct CommandResponse {
+    ok: bool,
+    replies: Vec<String>,
+}
+
+async fn command(
+    State(api): State<Api>,
+    Json(request): Json<CommandRequest>,
+) -> (StatusCode, Json<CommandResponse>) {
+    let replies = Arc::new(Mutex::new(Vec::new()));
+    let source = CommandSource::new(api.swarm, api.state, Origin::Api(replies.clone()));
+    let command = request.command.trim_start_matches('!');
+    let ok = source.run(command);
+    let status = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    let replies = replies.lock().clone();
+    (status, Json(CommandResponse { ok, replies }))
+}
+
+#[derive(Serialize)]
+struct TaskList {
+    queued: Vec<QueuedTaskSnapshot>,
+    running: Vec<RunningTaskView>,
+}
+
+#[derive(Serialize)]
+struct RunningTaskView {
+    bot: String,
+    task: TaskSnapshot,
+    progress: Option<TaskProgress>,
+}
+
+async fn tasks(State(api): State<Api>) -> Json<TaskList> {
+    let queued = api
+        .state
+        .tasks
+        .lock()
+        .iter()
+        .map(QueuedTask::snapshot)
+        .collect();
+    let running = api
+        .swarm
+        .clone()
+        .into_iter()
+        .filter_map(|bot| {
+            let bot_state = bot.get_component::<BotState>()?;
+            let task = bot_state.task.lock();
+            let task = task.as_ref()?;
+            Some(RunningTaskView {
+                bot: bot.username(),
+                task: task.snapshot(),
+                progress: task.progress(),
+            })
+        })
+        .collect();
+    Json(TaskList { queued, running })
+}
+
+#[derive(Serialize)]
+struct TaskType {
+    #[serde(flatten)]
+    spec: &'static TaskSpec,
+    usages: Vec<String>,
+}
+
+async fn task_types() -> Json<Vec<TaskType>> {
+    Json(
+        bot_task::TASKS
+            .iter()
+            .copied()
+            .map(|spec| TaskType {
+                spec,
+                usages: spec.usages(),
+            })
+            .collect(),
+    )
+}
+
+#[derive(Serialize)]
+struct Count {
+    count: usize,
+}
+
+async fn cancel_tasks(State(api): State<Api>) -> Json<Count> {
+    Json(Count {
+        count: cancel_all(&api.swarm),
+    })
+}
+
+async fn cancel_task(State(api): State<Api>, Path(bot): Path<String>) -> StatusCode {
+    match cancel_bot(&api.swarm, bot.as_str()) {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(NotCancelled::NoSuchBot) => StatusCode::NOT_FOUND,
+        Err(NotCancelled::Idle) => StatusCode::CONFLICT,
+    }
+}
+
+async fn clear_queue(State(api): State<Api>) -> Json<Count> {
+    Json(Count {
+        count: api.state.clear_queue(),
+    })
+}
+
+async fn remove_queued(State(api): State<Api>, Path(index): Path<usize>) -> StatusCode {
+    match api.state.remove_queued(index) {
+        Some(_) => StatusCode::NO_CONTENT,
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+/// The swarm's events that pass `filter`. Subscribers that fall too far
+/// behind skip the events they missed.
+fn subscribe(api: &Api, filter: EventFilter) -> impl Stream<Item = SwarmEventKind> + use<> {
+    BroadcastStream::new(api.state.events.subscribe())
+        .filter_map(move |event| event.ok().filter(|event| filter.matches(event)))
+}
+
+async fn events(
+    State(api): State<Api>,
+    Query(filter): Query<EventFilter>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let stream =
+        subscribe(&api, filter).filter_map(|event| Event::default().json_data(event).ok().map(Ok));
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn events_ws(
+    State(api): State<Api>,
+    Query(filter): Query<EventFilter>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let stream = subscribe(&api, filter);
+    upgrade.on_upgrade(|socket| forward_events(socket, stream))
+}
+
+/// Sends events until the client goes away.
+async fn forward_events(mut socket: WebSocket, stream: impl Stream<Item = SwarmEventKind>) {
+    let mut stream = std::pin::pin!(stream);
+    while let Some(event) = stream.next().await {
+        let Ok(json) = serde_json::to_string(&event) else {
+            continue;
+        };
+        if socket.send(Message::Text(json.into())).await.is_err() {
+            return;
+        }
+    }
+}
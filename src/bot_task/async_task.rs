@@ -0,0 +1,247 @@
+//! Tasks written as straight-line async code instead of a state machine. The
+//! body runs on its own tokio task with a [`TaskContext`], while the
+//! [`AsyncTask`] wrapping it is driven by the runner like any other task and
+//! reports its outcome once the body returns. Cancelling aborts the body at
+//! whatever it was awaiting.
+
+use std::{future::Future, pin::Pin, sync::Arc};
+
+use azalea::{
+    BlockPos, Client, Vec3, WalkDirection, chat::ChatPacket, pathfinder::goals::Goal, prelude::*,
+};
+use parking_lot::Mutex;
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    task::JoinHandle,
+};
+use tracing::Instrument;
+
+use crate::{
+    command_controler::{BotTask, TaskEvent, TaskLimits, TaskOutcome},
+    metrics,
+    persistence::TaskSnapshot,
+};
+
+/// Events that can pile up while the body is busy awaiting something else,
+/// like a goto. Past that the oldest are dropped.
+static EVENT_CAPACITY: usize = 64;
+
+pub type TaskFuture = Pin<Box<dyn Future<Output = TaskOutcome> + Send>>;
+
+/// What the body of an [`AsyncTask`] gets to drive its bot with.
+pub struct TaskContext {
+    pub bot: Client,
+    events: broadcast::Receiver<TaskEvent>,
+}
+
+impl TaskContext {
+    /// Pathfinds to the goal, returning once the bot is there.
+    pub async fn goto(&self, goal: impl Goal + 'static) {
+        self.bot.goto(goal).await;
+    }
+
+    /// Mines the block, returning once it's broken.
+    pub async fn mine(&self, position: BlockPos) {
+        self.bot.mine(position).await;
+    }
+
+    pub async fn wait_ticks(&self, ticks: usize) {
+        self.bot.wait_ticks(ticks).await;
+    }
+
+    /// Waits for the next chat message `matches` accepts.
+    pub async fn wait_for_chat(&mut self, matches: impl Fn(&ChatPacket) -> bool) -> ChatPacket {
+        next_chat(&mut self.events, matches).await
+    }
+
+    pub fn chat(&self, message: &str) {
+        metrics::chat(&self.bot, message);
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.bot.position()
+    }
+}
+
+async fn next_chat(
+    events: &mut broadcast::Receiver<TaskEvent>,
+    matches: impl Fn(&ChatPacket) -> bool,
+) -> ChatPacket {
+    loop {
+        match events.recv().await {
+            Ok(TaskEvent::Chat(packet)) if matches(&packet) => return packet,
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            // the task is gone, so the body is about to be aborted anyway
+            Err(RecvError::Closed) => std::future::pending().await,
+        }
+    }
+}
+
+/// A task whose body is an `async fn(TaskContext) -> TaskOutcome`.
+pub struct AsyncTask {
+    name: &'static str,
+    body: Arc<dyn Fn(TaskContext) -> TaskFuture + Send + Sync>,
+    /// What's saved for it. The body can't be saved partway through, so a
+    /// restored task starts over.
+    snapshot: TaskSnapshot,
+    limits: TaskLimits,
+    running: Option<Running>,
+}
+
+struct Running {
+    handle: JoinHandle<()>,
+    outcome: Arc<Mutex<Option<TaskOutcome>>>,
+    events: broadcast::Sender<TaskEvent>,
+}
+
+impl AsyncTask {
+    pub fn new<F, Fut>(name: &'static str, snapshot: TaskSnapshot, body: F) -> Self
+    where
+        F: Fn(TaskContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskOutcome> + Send + 'static,
+    {
+        Self {
+            name,
+            body: Arc::new(move |ctx| Box::pin(body(ctx))),
+            snapshot,
+            limits: TaskLimits::default(),
+            running: None,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: TaskLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+// The body is shared, but whatever run of it is going on isn't, so a copy
+// starts its own from the beginning.
+impl Clone for AsyncTask {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name,
+            body: self.body.clone(),
+            snapshot: self.snapshot.clone(),
+            limits: self.limits,
+            running: None,
+        }
+    }
+}
+
+// Otherwise a task that's dropped without being cancelled, like one taken
+// off a bot that disconnected, would leave its body driving the old client.
+impl Drop for AsyncTask {
+    fn drop(&mut self) {
+        if let Some(running) = &self.running {
+            running.handle.abort();
+        }
+    }
+}
+
+impl BotTask for AsyncTask {
+    fn get_name(&self) -> &str {
+        self.name
+    }
+    fn on_start(&mut self, bot: &Client) {
+        let (events, receiver) = broadcast::channel(EVENT_CAPACITY);
+        let outcome = Arc::new(Mutex::new(None));
+        let body = (self.body)(TaskContext {
+            bot: bot.clone(),
+            events: receiver,
+        });
+        let slot = outcome.clone();
+        // Inherits the task's span, so the body logs like the task itself
+        let handle = tokio::spawn(
+            async move {
+                let result = body.await;
+                *slot.lock() = Some(result);
+            }
+            .in_current_span(),
+        );
+        self.running = Some(Running {
+            handle,
+            outcome,
+            events,
+        });
+    }
+    fn on_tick(&mut self, _bot: &Client) {}
+    fn on_event(&mut self, _bot: &Client, event: &TaskEvent) {
+        if let Some(running) = &self.running {
+            let _ = running.events.send(event.clone());
+        }
+    }
+    fn on_cancel(&mut self, bot: &Client) {
+        if let Some(running) = &self.running {
+            running.handle.abort();
+        }
+        bot.stop_pathfinding();
+        bot.walk(WalkDirection::None);
+    }
+
+    fn end(&self) -> Option<TaskOutcome> {
+        let running = self.running.as_ref()?;
+        if running.handle.is_finished() {
+            // finished without an outcome means the body panicked
+            return Some(
+                running
+                    .outcome
+                    .lock()
+                    .clone()
+                    .unwrap_or_else(|| TaskOutcome::Failed("the task panicked".to_string())),
+            );
+        }
+        running.outcome.lock().clone()
+    }
+
+    fn default_limits(&self) -> TaskLimits {
+        self.limits
+    }
+
+    fn snapshot(&self) -> TaskSnapshot {
+        self.snapshot.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn chat(message: &str) -> TaskEvent {
+        TaskEvent::Chat(ChatPacket::new(message))
+    }
+
+    #[tokio::test]
+    async fn waits_for_a_matching_message() {
+        let (events, mut receiver) = broadcast::channel(EVENT_CAPACITY);
+        events.send(TaskEvent::Death).unwrap();
+        events.send(chat("not yet")).unwrap();
+        events.send(chat("ready")).unwrap();
+        let packet = next_chat(&mut receiver, |packet| packet.content() == "ready").await;
+        assert_eq!(packet.content(), "ready");
+    }
+
+    #[tokio::test]
+    async fn skips_what_it_fell_behind_on() {
+        let (events, mut receiver) = broadcast::channel(2);
+        for message in ["one", "two", "three"] {
+            events.send(chat(message)).unwrap();
+        }
+        let packet = next_chat(&mut receiver, |_| true).await;
+        assert_eq!(packet.content(), "two");
+    }
+
+    #[tokio::test]
+    async fn never_returns_once_the_task_is_gone() {
+        let (events, mut receiver) = broadcast::channel(EVENT_CAPACITY);
+        drop(events);
+        let waited = tokio::time::timeout(
+            Duration::from_millis(50),
+            next_chat(&mut receiver, |_| true),
+        )
+        .await;
+        assert!(waited.is_err());
+    }
+}
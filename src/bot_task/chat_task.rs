@@ -1,41 +1,94 @@
 use std::time::{Duration, Instant};
 
-use azalea::{Client, Event};
+use azalea::{Client, brigadier::prelude::*};
 
-use crate::command_controler::BotTask;
+use crate::{
+    SwarmState,
+    bot_task::registry::{Arg, TaskSpec},
+    command_controler::{BotTask, TaskOutcome, TaskProgress},
+    commands::{CommandSource, Ctx},
+    metrics,
+    persistence::TaskSnapshot,
+};
 
+#[derive(Clone)]
 pub struct Chat {
-    last_update: Instant,
+    last_update: Option<Instant>,
+    delay: Duration,
     messages: Vec<String>,
     index: usize,
 }
 
-static DELAY: Duration = Duration::from_secs(1);
-
 impl Chat {
     pub fn init(args: Vec<String>) -> Self {
         Self {
-            last_update: Instant::now() - DELAY,
+            last_update: None,
+            delay: Duration::ZERO,
             messages: args,
             index: 0,
         }
     }
+
+    /// Picks up at `index`, the next message that hadn't been sent.
+    pub fn resume(messages: Vec<String>, index: usize) -> Self {
+        Self {
+            index: index.min(messages.len()),
+            ..Self::init(messages)
+        }
+    }
+}
+
+pub static SPEC: TaskSpec = TaskSpec {
+    name: "chat",
+    aliases: &[],
+    forms: &[&[Arg::Greedy("messages")]],
+    greedy_forms: &[],
+    help: "Sends each word as its own message, waiting the chat delay between them.",
+    build,
+};
+
+fn build(ctx: &Ctx, _source: &CommandSource) -> Result<Box<dyn BotTask>, String> {
+    let messages = get_string(ctx, "messages").unwrap();
+    Ok(Box::new(Chat::init(
+        messages.split_whitespace().map(|s| s.to_string()).collect(),
+    )))
 }
 
 impl BotTask for Chat {
     fn get_name(&self) -> &str {
         "Chat"
     }
-    fn on_event(&mut self, bot: &Client, _event: &Event) {
-        if self.last_update.elapsed() >= DELAY {
-            bot.chat(self.messages[self.index].as_str());
+    fn on_start(&mut self, bot: &Client) {
+        // Picked up here so a reloaded config applies to the next chat task
+        self.delay = bot.resource::<SwarmState>().config.read().chat_delay();
+    }
+    fn on_tick(&mut self, bot: &Client) {
+        if self
+            .last_update
+            .is_none_or(|last_update| last_update.elapsed() >= self.delay)
+        {
+            metrics::chat(bot, self.messages[self.index].as_str());
             self.index += 1;
-            self.last_update = Instant::now();
+            self.last_update = Some(Instant::now());
         }
     }
 
-    fn end(&self) -> bool {
-        self.index == self.messages.len()
-        // Clean up
+    fn end(&self) -> Option<TaskOutcome> {
+        (self.index == self.messages.len()).then_some(TaskOutcome::Success)
+    }
+
+    fn progress(&self) -> Option<TaskProgress> {
+        Some(TaskProgress::count(
+            self.index,
+            self.messages.len(),
+            "messages",
+        ))
+    }
+
+    fn snapshot(&self) -> TaskSnapshot {
+        TaskSnapshot::Chat {
+            messages: self.messages.clone(),
+            index: self.index,
+        }
     }
 }
@@ -0,0 +1,466 @@
+use azalea::{Client, brigadier::prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bot_task::registry::{Arg, TaskSpec},
+    command_controler::{BotTask, RunningTask, TaskEvent, TaskOutcome, TaskProgress},
+    persistence::TaskSnapshot,
+};
+
+pub static SEQUENCE: TaskSpec = TaskSpec {
+    name: "seq",
+    aliases: &[],
+    forms: &[&[Arg::Greedy("steps")]],
+    greedy_forms: &[],
+    help: "Runs ; separated tasks one after another, e.g. !seq goto 0 0 ; chat hi.",
+    build: |ctx, source| {
+        let steps = source.parse_steps(get_string(ctx, "steps").unwrap().as_str())?;
+        Ok(Box::new(Sequence::new(steps)))
+    },
+};
+
+pub static PARALLEL: TaskSpec = TaskSpec {
+    name: "par",
+    aliases: &[],
+    forms: &[&[Arg::Greedy("tasks")]],
+    greedy_forms: &[],
+    help: "Runs ; separated tasks at the same time until all of them succeed.",
+    build: |ctx, source| {
+        let children = source.parse_steps(get_string(ctx, "tasks").unwrap().as_str())?;
+        Ok(Box::new(Parallel::new(children, WaitFor::All)))
+    },
+};
+
+pub static RACE: TaskSpec = TaskSpec {
+    name: "race",
+    aliases: &[],
+    forms: &[&[Arg::Greedy("tasks")]],
+    greedy_forms: &[],
+    help: "Runs ; separated tasks at the same time until one of them succeeds.",
+    build: |ctx, source| {
+        let children = source.parse_steps(get_string(ctx, "tasks").unwrap().as_str())?;
+        Ok(Box::new(Parallel::new(children, WaitFor::Any)))
+    },
+};
+
+pub static REPEAT: TaskSpec = TaskSpec {
+    name: "repeat",
+    aliases: &[],
+    forms: &[
+        &[Arg::Literal("forever"), Arg::Greedy("task")],
+        &[Arg::Integer("times"), Arg::Greedy("task")],
+    ],
+    greedy_forms: &[],
+    help: "Runs a task again every time it succeeds.",
+    build: |ctx, source| {
+        let times = match get_integer(ctx, "times") {
+            Some(times) => match u32::try_from(times) {
+                Ok(times) if times > 0 => Some(times),
+                _ => return Err(format!("Invalid repeat count: {}", times)),
+            },
+            None => None,
+        };
+        let task = source.parse_step(get_string(ctx, "task").unwrap().as_str())?;
+        Ok(Box::new(Repeat::new(task, times)))
+    },
+};
+
+/// Runs its steps one after another, failing as soon as one of them fails.
+pub struct Sequence {
+    steps: Vec<Box<dyn BotTask>>,
+    index: usize,
+    current: Option<RunningTask>,
+    outcome: Option<TaskOutcome>,
+}
+
+impl Sequence {
+    pub fn new(steps: Vec<Box<dyn BotTask>>) -> Self {
+        Self {
+            steps,
+            index: 0,
+            current: None,
+            outcome: None,
+        }
+    }
+
+    fn start_step(&mut self, bot: &Client) {
+        match self.steps.get(self.index) {
+            Some(step) => self.current = Some(RunningTask::start_child(step.as_ref(), bot)),
+            None => {
+                self.current = None;
+                self.outcome = Some(TaskOutcome::Success);
+            }
+        }
+    }
+}
+
+// Clones are only taken of queued tasks, so a copy starts from the first step.
+impl Clone for Sequence {
+    fn clone(&self) -> Self {
+        Self::new(self.steps.clone())
+    }
+}
+
+impl BotTask for Sequence {
+    fn get_name(&self) -> &str {
+        "Sequence"
+    }
+    fn on_start(&mut self, bot: &Client) {
+        self.start_step(bot);
+    }
+    fn on_tick(&mut self, bot: &Client) {
+        let Some(current) = self.current.as_mut() else {
+            return;
+        };
+        match current.tick(bot) {
+            None => {}
+            Some(TaskOutcome::Success) => {
+                self.index += 1;
+                self.start_step(bot);
+            }
+            Some(TaskOutcome::Failed(reason)) => {
+                self.outcome = Some(TaskOutcome::Failed(format!(
+                    "step {} ({}) {}",
+                    self.index + 1,
+                    current.get_name(),
+                    reason
+                )));
+                self.current = None;
+            }
+            Some(TaskOutcome::Cancelled) => {
+                self.outcome = Some(TaskOutcome::Cancelled);
+                self.current = None;
+            }
+        }
+    }
+    fn on_event(&mut self, bot: &Client, event: &TaskEvent) {
+        if let Some(current) = self.current.as_mut() {
+            current.deliver(bot, event);
+        }
+    }
+    fn on_cancel(&mut self, bot: &Client) {
+        if let Some(current) = self.current.as_mut() {
+            current.cancel(bot);
+        }
+    }
+
+    fn end(&self) -> Option<TaskOutcome> {
+        self.outcome.clone()
+    }
+
+    fn progress(&self) -> Option<TaskProgress> {
+        let current = self.current.as_ref()?;
+        let done = current.progress().and_then(|progress| progress.fraction);
+        Some(TaskProgress::new(
+            Some((self.index as f32 + done.unwrap_or(0.0)) / self.steps.len() as f32),
+            format!(
+                "step {}/{}, {}",
+                self.index + 1,
+                self.steps.len(),
+                current.describe()
+            ),
+        ))
+    }
+
+    fn snapshot(&self) -> TaskSnapshot {
+        let remaining = match &self.current {
+            Some(current) => {
+                let rest = self.steps.iter().skip(self.index + 1);
+                std::iter::once(current.snapshot())
+                    .chain(rest.map(|step| step.snapshot()))
+                    .collect()
+            }
+            None => self
+                .steps
+                .iter()
+                .skip(self.index)
+                .map(|step| step.snapshot())
+                .collect(),
+        };
+        TaskSnapshot::Sequence { steps: remaining }
+    }
+}
+
+/// When a [`Parallel`] group is done.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WaitFor {
+    /// Every child has to succeed; the first failure fails the group.
+    All,
+    /// The first child to succeed ends the group and cancels the rest.
+    Any,
+}
+
+/// Runs all of its children at the same time on one bot.
+pub struct Parallel {
+    children: Vec<Box<dyn BotTask>>,
+    wait_for: WaitFor,
+    running: Vec<(RunningTask, Option<TaskOutcome>)>,
+    outcome: Option<TaskOutcome>,
+}
+
+impl Parallel {
+    pub fn new(children: Vec<Box<dyn BotTask>>, wait_for: WaitFor) -> Self {
+        Self {
+            children,
+            wait_for,
+            running: Vec::new(),
+            outcome: None,
+        }
+    }
+
+    fn finish(&mut self, bot: &Client, outcome: TaskOutcome) {
+        for (child, result) in self.running.iter_mut() {
+            if result.is_none() {
+                child.cancel(bot);
+            }
+        }
+        self.running.clear();
+        self.outcome = Some(outcome);
+    }
+}
+
+impl Clone for Parallel {
+    fn clone(&self) -> Self {
+        Self::new(self.children.clone(), self.wait_for)
+    }
+}
+
+impl BotTask for Parallel {
+    fn get_name(&self) -> &str {
+        "Parallel"
+    }
+    fn on_start(&mut self, bot: &Client) {
+        self.running = self
+            .children
+            .iter()
+            .map(|child| (RunningTask::start_child(child.as_ref(), bot), None))
+            .collect();
+        if self.running.is_empty() {
+            self.outcome = Some(TaskOutcome::Success);
+        }
+    }
+    fn on_tick(&mut self, bot: &Client) {
+        for (child, result) in self.running.iter_mut() {
+            if result.is_none() {
+                *result = child.tick(bot);
+            }
+        }
+
+        let mut pending = false;
+        let mut failure = None;
+        let mut succeeded = false;
+        for (child, result) in &self.running {
+            match result {
+                None => pending = true,
+                Some(TaskOutcome::Success) => succeeded = true,
+                Some(TaskOutcome::Failed(reason)) => {
+                    failure = Some(format!("{} {}", child.get_name(), reason))
+                }
+                Some(TaskOutcome::Cancelled) => {
+                    failure = Some(format!("{} was cancelled", child.get_name()))
+                }
+            }
+        }
+
+        match self.wait_for {
+            WaitFor::All => {
+                if let Some(reason) = failure {
+                    self.finish(bot, TaskOutcome::Failed(reason));
+                } else if !pending {
+                    self.finish(bot, TaskOutcome::Success);
+                }
+            }
+            WaitFor::Any => {
+                if succeeded {
+                    self.finish(bot, TaskOutcome::Success);
+                } else if !pending {
+                    let reason = failure.unwrap_or_else(|| "no task succeeded".to_string());
+                    self.finish(bot, TaskOutcome::Failed(reason));
+                }
+            }
+        }
+    }
+    fn on_event(&mut self, bot: &Client, event: &TaskEvent) {
+        for (child, result) in self.running.iter_mut() {
+            if result.is_none() {
+                child.deliver(bot, event);
+            }
+        }
+    }
+    fn on_cancel(&mut self, bot: &Client) {
+        for (child, result) in self.running.iter_mut() {
+            if result.is_none() {
+                child.cancel(bot);
+            }
+        }
+    }
+
+    fn end(&self) -> Option<TaskOutcome> {
+        self.outcome.clone()
+    }
+
+    fn progress(&self) -> Option<TaskProgress> {
+        let done = self
+            .running
+            .iter()
+            .filter(|(_, result)| result.is_some())
+            .count();
+        (!self.running.is_empty()).then(|| TaskProgress::count(done, self.running.len(), "done"))
+    }
+
+    fn snapshot(&self) -> TaskSnapshot {
+        let children = if self.running.is_empty() {
+            self.children.iter().map(|child| child.snapshot()).collect()
+        } else {
+            self.running
+                .iter()
+                .filter(|(_, result)| result.is_none())
+                .map(|(child, _)| child.snapshot())
+                .collect()
+        };
+        TaskSnapshot::Parallel {
+            children,
+            wait_for: self.wait_for,
+        }
+    }
+}
+
+/// Runs a task again each time it succeeds, `times` times or forever.
+pub struct Repeat {
+    template: Box<dyn BotTask>,
+    times: Option<u32>,
+    iteration: u32,
+    current: Option<RunningTask>,
+    /// The interrupted run to finish before starting the next one, when
+    /// restored from a snapshot.
+    resume: Option<Box<dyn BotTask>>,
+    outcome: Option<TaskOutcome>,
+}
+
+impl Repeat {
+    pub fn new(template: Box<dyn BotTask>, times: Option<u32>) -> Self {
+        Self {
+            template,
+            times,
+            iteration: 0,
+            current: None,
+            resume: None,
+            outcome: None,
+        }
+    }
+
+    /// Continues from `iteration`, finishing the `current` run first.
+    pub fn resume(
+        template: Box<dyn BotTask>,
+        times: Option<u32>,
+        iteration: u32,
+        current: Option<Box<dyn BotTask>>,
+    ) -> Self {
+        Self {
+            iteration,
+            resume: current,
+            ..Self::new(template, times)
+        }
+    }
+
+    fn start_iteration(&mut self, bot: &Client) {
+        if self.times.is_some_and(|times| self.iteration >= times) {
+            self.current = None;
+            self.outcome = Some(TaskOutcome::Success);
+        } else if let Some(resume) = self.resume.take() {
+            self.current = Some(RunningTask::start_child(resume.as_ref(), bot));
+        } else {
+            self.current = Some(RunningTask::start_child(self.template.as_ref(), bot));
+        }
+    }
+}
+
+impl Clone for Repeat {
+    fn clone(&self) -> Self {
+        Self::resume(
+            self.template.clone(),
+            self.times,
+            self.iteration,
+            self.resume.clone(),
+        )
+    }
+}
+
+impl BotTask for Repeat {
+    fn get_name(&self) -> &str {
+        "Repeat"
+    }
+    fn on_start(&mut self, bot: &Client) {
+        self.start_iteration(bot);
+    }
+    fn on_tick(&mut self, bot: &Client) {
+        let Some(current) = self.current.as_mut() else {
+            return;
+        };
+        match current.tick(bot) {
+            None => {}
+            Some(TaskOutcome::Success) => {
+                self.iteration += 1;
+                self.start_iteration(bot);
+            }
+            Some(TaskOutcome::Failed(reason)) => {
+                self.outcome = Some(TaskOutcome::Failed(format!(
+                    "run {} {}",
+                    self.iteration + 1,
+                    reason
+                )));
+                self.current = None;
+            }
+            Some(TaskOutcome::Cancelled) => {
+                self.outcome = Some(TaskOutcome::Cancelled);
+                self.current = None;
+            }
+        }
+    }
+    fn on_event(&mut self, bot: &Client, event: &TaskEvent) {
+        if let Some(current) = self.current.as_mut() {
+            current.deliver(bot, event);
+        }
+    }
+    fn on_cancel(&mut self, bot: &Client) {
+        if let Some(current) = self.current.as_mut() {
+            current.cancel(bot);
+        }
+    }
+
+    fn end(&self) -> Option<TaskOutcome> {
+        self.outcome.clone()
+    }
+
+    fn progress(&self) -> Option<TaskProgress> {
+        let current = self.current.as_ref()?;
+        let run = match self.times {
+            Some(times) => format!("run {}/{}", self.iteration + 1, times),
+            None => format!("run {}", self.iteration + 1),
+        };
+        let fraction = self.times.map(|times| {
+            let done = current
+                .progress()
+                .and_then(|progress| progress.fraction)
+                .unwrap_or(0.0);
+            (self.iteration as f32 + done) / times as f32
+        });
+        Some(TaskProgress::new(
+            fraction,
+            format!("{}, {}", run, current.describe()),
+        ))
+    }
+
+    fn snapshot(&self) -> TaskSnapshot {
+        TaskSnapshot::Repeat {
+            template: Box::new(self.template.snapshot()),
+            times: self.times,
+            iteration: self.iteration,
+            current: self
+                .current
+                .as_ref()
+                .map(|current| Box::new(current.snapshot())),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+use azalea::{Client, brigadier::prelude::*};
+
+use crate::{
+    bot_task::registry::{Arg, TaskSpec},
+    command_controler::{BotTask, TaskOutcome, TaskProgress},
+    commands::{CommandSource, Ctx},
+    persistence::TaskSnapshot,
+};
+
+#[derive(Clone)]
+pub struct Delay {
+    duration: Duration,
+    started_at: Option<Instant>,
+}
+
+impl Delay {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            duration,
+            started_at: None,
+        }
+    }
+}
+
+pub static SPEC: TaskSpec = TaskSpec {
+    name: "delay",
+    aliases: &["wait"],
+    forms: &[&[Arg::Float("seconds")]],
+    greedy_forms: &[],
+    help: "Does nothing for a while, mostly useful as a step of seq.",
+    build,
+};
+
+fn build(ctx: &Ctx, _source: &CommandSource) -> Result<Box<dyn BotTask>, String> {
+    let seconds = get_float(ctx, "seconds").unwrap();
+    let duration =
+        Duration::try_from_secs_f32(seconds).map_err(|_| format!("Invalid delay: {}", seconds))?;
+    Ok(Box::new(Delay::new(duration)))
+}
+
+impl BotTask for Delay {
+    fn get_name(&self) -> &str {
+        "Delay"
+    }
+    fn on_start(&mut self, _bot: &Client) {
+        self.started_at = Some(Instant::now());
+    }
+    fn on_tick(&mut self, _bot: &Client) {}
+
+    fn end(&self) -> Option<TaskOutcome> {
+        self.started_at
+            .is_some_and(|started_at| started_at.elapsed() >= self.duration)
+            .then_some(TaskOutcome::Success)
+    }
+
+    fn progress(&self) -> Option<TaskProgress> {
+        let elapsed = self.started_at?.elapsed().min(self.duration);
+        let fraction =
+            (!self.duration.is_zero()).then(|| elapsed.as_secs_f32() / self.duration.as_secs_f32());
+        Some(TaskProgress::new(
+            fraction,
+            format!("{:.1}s left", (self.duration - elapsed).as_secs_f32()),
+        ))
+    }
+
+    fn snapshot(&self) -> TaskSnapshot {
+        let elapsed = self
+            .started_at
+            .map_or(Duration::ZERO, |started_at| started_at.elapsed());
+        TaskSnapshot::Delay {
+            remaining_ms: self.duration.saturating_sub(elapsed).as_millis() as u64,
+        }
+    }
+}
@@ -0,0 +1,399 @@
+//! Where a goto is headed, as typed: `x y z`, `x z`, Minecraft style `~` and
+//! `^` coordinates, `@player` or a waypoint from the config. It's only turned
+//! into a block once a bot picks the task up, so `~` means wherever that bot
+//! is then.
+
+use std::fmt::{self, Display};
+
+use azalea::{
+    Client, Vec3,
+    ecs::prelude::*,
+    entity::{EntityUuid, LookDirection, Position},
+    world::InstanceName,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::SwarmState;
+
+/// One coordinate: `12`, `~-3` or `^2`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Coordinate {
+    Absolute(f64),
+    /// Added to the anchor's position.
+    Relative(f64),
+    /// Along the way the anchor is facing: left, up and forward.
+    Local(f64),
+}
+
+impl Coordinate {
+    pub fn parse(arg: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid coordinate: {}", arg);
+        let offset = |rest: &str| {
+            if rest.is_empty() {
+                Ok(0.0)
+            } else {
+                rest.parse::<f64>().map_err(|_| invalid())
+            }
+        };
+        if let Some(rest) = arg.strip_prefix('~') {
+            Ok(Coordinate::Relative(offset(rest)?))
+        } else if let Some(rest) = arg.strip_prefix('^') {
+            Ok(Coordinate::Local(offset(rest)?))
+        } else {
+            arg.parse::<i32>()
+                .map(|value| Coordinate::Absolute(value as f64))
+                .map_err(|_| invalid())
+        }
+    }
+
+    fn is_local(&self) -> bool {
+        matches!(self, Coordinate::Local(_))
+    }
+
+    fn apply(&self, origin: f64) -> f64 {
+        match *self {
+            Coordinate::Absolute(value) => value,
+            // parsing makes sure ^ never gets here mixed with the others
+            Coordinate::Relative(offset) | Coordinate::Local(offset) => origin + offset,
+        }
+    }
+}
+
+impl Display for Coordinate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (prefix, value) = match *self {
+            Coordinate::Absolute(value) => ("", value),
+            Coordinate::Relative(offset) => ("~", offset),
+            Coordinate::Local(offset) => ("^", offset),
+        };
+        if value == 0.0 && !prefix.is_empty() {
+            write!(f, "{}", prefix)
+        } else {
+            write!(f, "{}{}", prefix, value)
+        }
+    }
+}
+
+/// What `~` and `^` are relative to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Anchor {
+    /// The bot running the task.
+    Bot,
+    Player(String),
+    /// A name from `[waypoints]` in the config.
+    Waypoint(String),
+}
+
+impl Anchor {
+    /// `@me` is the sender of the command, `@<player>` any other player and
+    /// anything else a waypoint.
+    pub fn parse(arg: &str, sender: Option<&str>) -> Result<Self, String> {
+        match arg.strip_prefix('@') {
+            Some("me") => sender
+                .map(|sender| Anchor::Player(sender.to_string()))
+                .ok_or_else(|| "@me only works from chat".to_string()),
+            Some("") => Err("Missing a player name after @".to_string()),
+            Some(player) => Ok(Anchor::Player(player.to_string())),
+            None => Ok(Anchor::Waypoint(arg.to_string())),
+        }
+    }
+
+    /// Its position and the way it's facing, if it faces anywhere.
+    fn locate(&self, bot: &Client) -> Result<(Vec3, Option<LookDirection>), String> {
+        match self {
+            Anchor::Bot => Ok((bot.position(), Some(bot.component::<LookDirection>()))),
+            Anchor::Player(name) => find_player(bot, name)
+                .map(|(position, look)| (position, Some(look)))
+                .ok_or_else(|| format!("I can't see {}", name)),
+            Anchor::Waypoint(name) => {
+                let waypoint = bot
+                    .resource::<SwarmState>()
+                    .config
+                    .read()
+                    .waypoints
+                    .get(name)
+                    .copied();
+                let [x, y, z] =
+                    waypoint.ok_or_else(|| format!("There's no waypoint called {}", name))?;
+                Ok((Vec3::new(x as f64, y as f64, z as f64), None))
+            }
+        }
+    }
+}
+
+impl Display for Anchor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anchor::Bot => write!(f, "the bot"),
+            Anchor::Player(name) => write!(f, "@{}", name),
+            Anchor::Waypoint(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// The block a destination came down to. Without a y any height will do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockTarget {
+    pub x: i32,
+    pub y: Option<i32>,
+    pub z: i32,
+}
+
+impl Display for BlockTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.y {
+            Some(y) => write!(f, "{} {} {}", self.x, y, self.z),
+            None => write!(f, "{} {}", self.x, self.z),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Destination {
+    pub anchor: Anchor,
+    pub x: Coordinate,
+    pub y: Option<Coordinate>,
+    pub z: Coordinate,
+}
+
+impl Destination {
+    pub fn block(x: i32, y: Option<i32>, z: i32) -> Self {
+        Self {
+            anchor: Anchor::Bot,
+            x: Coordinate::Absolute(x as f64),
+            y: y.map(|y| Coordinate::Absolute(y as f64)),
+            z: Coordinate::Absolute(z as f64),
+        }
+    }
+
+    /// `<anchor>`, `<x> <z>`, `<x> <y> <z>` or `<anchor> <x> <y> <z>`.
+    pub fn parse(args: &[&str], sender: Option<&str>) -> Result<Self, String> {
+        let here = Coordinate::Relative(0.0);
+        let destination = match *args {
+            [anchor] => Self {
+                anchor: Anchor::parse(anchor, sender)?,
+                x: here,
+                y: Some(here),
+                z: here,
+            },
+            [x, z] => Self {
+                anchor: Anchor::Bot,
+                x: Coordinate::parse(x)?,
+                y: None,
+                z: Coordinate::parse(z)?,
+            },
+            [x, y, z] => Self {
+                anchor: Anchor::Bot,
+                x: Coordinate::parse(x)?,
+                y: Some(Coordinate::parse(y)?),
+                z: Coordinate::parse(z)?,
+            },
+            [anchor, x, y, z] => Self {
+                anchor: Anchor::parse(anchor, sender)?,
+                x: Coordinate::parse(x)?,
+                y: Some(Coordinate::parse(y)?),
+                z: Coordinate::parse(z)?,
+            },
+            _ => return Err("Expected a waypoint, @player or 2 to 3 coordinates".to_string()),
+        };
+
+        let coordinates = [Some(destination.x), destination.y, Some(destination.z)];
+        let locals = coordinates
+            .iter()
+            .filter(|coordinate| coordinate.is_some_and(|coordinate| coordinate.is_local()))
+            .count();
+        if locals != 0 && locals != 3 {
+            return Err("^ has to be used for all three coordinates".to_string());
+        }
+        Ok(destination)
+    }
+
+    /// The block to go to, as seen by `bot` now.
+    pub fn resolve(&self, bot: &Client) -> Result<BlockTarget, String> {
+        let (origin, look) = self.anchor.locate(bot)?;
+        let position = match (self.x, self.y, self.z) {
+            (Coordinate::Local(left), Some(Coordinate::Local(up)), Coordinate::Local(forward)) => {
+                let look = look.ok_or_else(|| {
+                    format!("{} doesn't face anywhere, so ^ can't be used", self.anchor)
+                })?;
+                origin + local_offset(&look, left, up, forward)
+            }
+            (x, y, z) => Vec3::new(
+                x.apply(origin.x),
+                y.map_or(origin.y, |y| y.apply(origin.y)),
+                z.apply(origin.z),
+            ),
+        };
+        Ok(BlockTarget {
+            x: position.x.floor() as i32,
+            y: self.y.map(|_| position.y.floor() as i32),
+            z: position.z.floor() as i32,
+        })
+    }
+}
+
+impl Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.anchor != Anchor::Bot {
+            write!(f, "{} ", self.anchor)?;
+        }
+        match self.y {
+            Some(y) => write!(f, "{} {} {}", self.x, y, self.z),
+            None => write!(f, "{} {}", self.x, self.z),
+        }
+    }
+}
+
+/// The same math as vanilla's `^left ^up ^forward`.
+fn local_offset(look: &LookDirection, left: f64, up: f64, forward: f64) -> Vec3 {
+    let yaw = (look.y_rot as f64 + 90.0).to_radians();
+    let pitch = (-look.x_rot as f64).to_radians();
+    let pitch_up = (-look.x_rot as f64 + 90.0).to_radians();
+
+    let forward_axis = Vec3::new(
+        yaw.cos() * pitch.cos(),
+        pitch.sin(),
+        yaw.sin() * pitch.cos(),
+    );
+    let up_axis = Vec3::new(
+        yaw.cos() * pitch_up.cos(),
+        pitch_up.sin(),
+        yaw.sin() * pitch_up.cos(),
+    );
+    // forward × up, flipped to point left
+    let left_axis = Vec3::new(
+        -(forward_axis.y * up_axis.z - forward_axis.z * up_axis.y),
+        -(forward_axis.z * up_axis.x - forward_axis.x * up_axis.z),
+        -(forward_axis.x * up_axis.y - forward_axis.y * up_axis.x),
+    );
+
+    Vec3::new(
+        forward_axis.x * forward + up_axis.x * up + left_axis.x * left,
+        forward_axis.y * forward + up_axis.y * up + left_axis.y * left,
+        forward_axis.z * forward + up_axis.z * up + left_axis.z * left,
+    )
+}
+
+/// Where the player called `name` is and where they're looking, if they're in
+/// the same world as the bot.
+fn find_player(bot: &Client, name: &str) -> Option<(Vec3, LookDirection)> {
+    let uuid = bot
+        .tab_list()
+        .into_iter()
+        .find(|(_, player_info)| player_info.profile.name == name)
+        .map(|(uuid, _)| uuid)?;
+    let bot_instance_name = bot.component::<InstanceName>();
+
+    let mut ecs = bot.ecs.lock();
+    let mut query = ecs.query::<(&EntityUuid, &Position, &LookDirection, &InstanceName)>();
+    query
+        .iter(&ecs)
+        .find(|(entity_uuid, _, _, instance_name)| {
+            ***entity_uuid == uuid && *instance_name == &bot_instance_name
+        })
+        .map(|(_, position, look, _)| (**position, *look))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Destination, String> {
+        Destination::parse(&args.split_whitespace().collect::<Vec<&str>>(), None)
+    }
+
+    #[test]
+    fn parses_every_form() {
+        let here = Coordinate::Relative(0.0);
+        assert_eq!(
+            parse("home"),
+            Ok(Destination {
+                anchor: Anchor::Waypoint("home".to_string()),
+                x: here,
+                y: Some(here),
+                z: here,
+            })
+        );
+        assert_eq!(parse("10 -20"), Ok(Destination::block(10, None, -20)));
+        assert_eq!(
+            parse("~ 64 ~-3.5"),
+            Ok(Destination {
+                anchor: Anchor::Bot,
+                x: here,
+                y: Some(Coordinate::Absolute(64.0)),
+                z: Coordinate::Relative(-3.5),
+            })
+        );
+        assert_eq!(
+            parse("@Steve ^ ^ ^5"),
+            Ok(Destination {
+                anchor: Anchor::Player("Steve".to_string()),
+                x: Coordinate::Local(0.0),
+                y: Some(Coordinate::Local(0.0)),
+                z: Coordinate::Local(5.0),
+            })
+        );
+        assert!(parse("").is_err());
+        assert!(parse("1 2 3 4 5").is_err());
+        assert!(parse("1.5 2").is_err());
+    }
+
+    #[test]
+    fn rejects_mixed_local_coordinates() {
+        assert!(parse("^ ~ ^").is_err());
+        assert!(parse("^1 ^2").is_err());
+        assert!(parse("home ^ ^ 3").is_err());
+    }
+
+    #[test]
+    fn needs_a_sender_for_me() {
+        assert!(parse("@me").is_err());
+        assert_eq!(
+            Destination::parse(&["@me"], Some("Alex")).map(|destination| destination.anchor),
+            Ok(Anchor::Player("Alex".to_string()))
+        );
+        assert!(parse("@").is_err());
+        assert!(parse("@ 1 2 3").is_err());
+    }
+
+    #[test]
+    fn displays_as_typed() {
+        assert_eq!(parse("@Steve ^ ^ ^5").unwrap().to_string(), "@Steve ^ ^ ^5");
+        assert_eq!(parse("~1 64 ~").unwrap().to_string(), "~1 64 ~");
+        assert_eq!(parse("10 -20").unwrap().to_string(), "10 -20");
+    }
+
+    fn assert_offset(y_rot: f32, x_rot: f32, left_up_forward: [f64; 3], expected: [f64; 3]) {
+        let [left, up, forward] = left_up_forward;
+        let offset = local_offset(&LookDirection { y_rot, x_rot }, left, up, forward);
+        let actual = [offset.x, offset.y, offset.z];
+        assert!(
+            actual
+                .iter()
+                .zip(expected)
+                .all(|(actual, expected)| (actual - expected).abs() < 1e-6),
+            "yaw {} pitch {}: {:?} isn't {:?}",
+            y_rot,
+            x_rot,
+            actual,
+            expected
+        );
+    }
+
+    // What `/tp ^ ^ ^1` and friends do in vanilla: yaw 0 faces south (+z)
+    // and 90 west (-x), pitch -90 is straight up.
+    #[test]
+    fn local_offsets_match_vanilla() {
+        assert_offset(0.0, 0.0, [0.0, 0.0, 1.0], [0.0, 0.0, 1.0]);
+        assert_offset(0.0, 0.0, [1.0, 0.0, 0.0], [1.0, 0.0, 0.0]);
+        assert_offset(0.0, 0.0, [0.0, 1.0, 0.0], [0.0, 1.0, 0.0]);
+        assert_offset(90.0, 0.0, [0.0, 0.0, 1.0], [-1.0, 0.0, 0.0]);
+        assert_offset(90.0, 0.0, [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]);
+        assert_offset(0.0, -90.0, [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]);
+        assert_offset(0.0, -90.0, [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]);
+        assert_offset(0.0, 90.0, [0.0, 0.0, 1.0], [0.0, -1.0, 0.0]);
+        assert_offset(0.0, 90.0, [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]);
+    }
+}
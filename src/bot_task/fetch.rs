@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use azalea::{BlockPos, brigadier::prelude::*, pathfinder::goals};
+
+use crate::{
+    bot_task::{
+        AsyncTask,
+        registry::{Arg, TaskSpec},
+    },
+    command_controler::{BotTask, TaskLimits, TaskOutcome},
+    commands::{CommandSource, Ctx},
+    persistence::TaskSnapshot,
+};
+
+static TIMEOUT: Duration = Duration::from_secs(600);
+/// How close the bot has to get to mine the block.
+static REACH: f32 = 4.0;
+/// Ticks to wait after mining for the drop to be picked up.
+static PICKUP_TICKS: usize = 20;
+
+pub static SPEC: TaskSpec = TaskSpec {
+    name: "fetch",
+    aliases: &[],
+    forms: &[&[Arg::Integer("x"), Arg::Integer("y"), Arg::Integer("z")]],
+    greedy_forms: &[],
+    help: "Goes to a block, mines it and comes back.",
+    build,
+};
+
+fn build(ctx: &Ctx, _source: &CommandSource) -> Result<Box<dyn BotTask>, String> {
+    let x = get_integer(ctx, "x").unwrap();
+    let y = get_integer(ctx, "y").unwrap();
+    let z = get_integer(ctx, "z").unwrap();
+    Ok(Box::new(fetch(BlockPos { x, y, z })))
+}
+
+/// Goes to the block, mines it and comes back to where it started.
+pub fn fetch(target: BlockPos) -> AsyncTask {
+    let snapshot = TaskSnapshot::Fetch {
+        x: target.x,
+        y: target.y,
+        z: target.z,
+    };
+    AsyncTask::new("Fetch", snapshot, move |ctx| async move {
+        let home = ctx.position().to_block_pos_floor();
+        ctx.chat(
+            format!(
+                "Fetching the block at {} {} {}",
+                target.x, target.y, target.z
+            )
+            .as_str(),
+        );
+
+        ctx.goto(goals::RadiusGoal {
+            pos: target.center(),
+            radius: REACH,
+        })
+        .await;
+        let block = ctx.bot.world().read().get_block_state(&target);
+        if block.is_none_or(|block| block.is_air()) {
+            return TaskOutcome::Failed("there's nothing to mine there".to_string());
+        }
+        ctx.mine(target).await;
+        ctx.wait_ticks(PICKUP_TICKS).await;
+
+        ctx.goto(goals::BlockPosGoal(home)).await;
+        TaskOutcome::Success
+    })
+    .with_limits(TaskLimits {
+        timeout: Some(TIMEOUT),
+        // standing still while mining is expected
+        stall_ticks: None,
+    })
+}
@@ -1,88 +1,198 @@
 use azalea::{
-    BlockPos, Client, Event,
-    pathfinder::{Pathfinder, goals},
+    BlockPos, Client, WalkDirection, brigadier::prelude::*, pathfinder::goals,
     prelude::PathfinderClientExt,
 };
+use std::time::{Duration, Instant};
 
-use crate::command_controler::BotTask;
+use crate::{
+    SwarmState,
+    bot_task::{
+        destination::{Anchor, BlockTarget, Destination},
+        registry::{Arg, TaskSpec},
+    },
+    command_controler::{BotTask, TaskLimits, TaskOutcome, TaskProgress},
+    commands::{CommandSource, Ctx},
+    metrics,
+    persistence::TaskSnapshot,
+};
+
+static TIMEOUT: Duration = Duration::from_secs(300);
+static STALL_TICKS: u32 = 100;
 
+#[derive(Clone)]
 pub struct GotoBlock {
-    x: i32,
-    y: Option<i32>,
-    z: i32,
-    started: bool,
+    destination: Destination,
+    /// The block the destination came down to when the task started.
+    target: Option<BlockTarget>,
+    /// Blocks to go when the task started, and on the last tick.
+    start_distance: Option<f64>,
+    remaining: Option<f64>,
+    started_at: Option<Instant>,
     finished: bool,
+    /// Why the destination couldn't be resolved.
+    failure: Option<String>,
 }
 
 impl GotoBlock {
-    pub fn parse(args: Vec<String>) -> Option<Self> {
-        if args.len() == 3 {
-            let x = args[0].parse().ok()?;
-            let y = args[1].parse().ok()?;
-            let z = args[2].parse().ok()?;
-            Some(Self {
-                x,
-                y: Some(y),
-                z,
-                started: false,
-                finished: false,
-            })
-        } else if args.len() == 2 {
-            let x = args[0].parse().ok()?;
-            let z = args[1].parse().ok()?;
-            Some(Self {
-                x,
-                y: None,
-                z,
-                started: false,
-                finished: false,
-            })
-        } else {
-            return None;
+    pub fn new(x: i32, y: Option<i32>, z: i32) -> Self {
+        Self::to(Destination::block(x, y, z))
+    }
+
+    pub fn to(destination: Destination) -> Self {
+        Self {
+            destination,
+            target: None,
+            start_distance: None,
+            remaining: None,
+            started_at: None,
+            finished: false,
+            failure: None,
         }
     }
+
+    /// Straight line distance left, ignoring y if no y was given.
+    fn distance_from(target: &BlockTarget, bot: &Client) -> f64 {
+        let pos = bot.position();
+        let dx = target.x as f64 + 0.5 - pos.x;
+        let dy = target.y.map_or(0.0, |y| y as f64 - pos.y);
+        let dz = target.z as f64 + 0.5 - pos.z;
+        (dx * dx + dy * dy + dz * dz).sqrt()
+    }
+}
+
+pub static SPEC: TaskSpec = TaskSpec {
+    name: "goto",
+    aliases: &[],
+    forms: &[&[Arg::Greedy("destination")]],
+    greedy_forms: &[
+        "<x> <z>",
+        "<x> <y> <z>",
+        "<waypoint>",
+        "@<player>",
+        "<waypoint> <x> <y> <z>",
+        "@<player> <x> <y> <z>",
+    ],
+    help: "Pathfinds to x y z, or x z at any height. ~ is relative to the bot and ^ is \
+           left, up and forward from where it's looking. @me, @<player> or a waypoint \
+           goes there, or is what ~ and ^ are relative to when put first.",
+    build,
+};
+
+fn build(ctx: &Ctx, source: &CommandSource) -> Result<Box<dyn BotTask>, String> {
+    let args = get_string(ctx, "destination").unwrap();
+    let args = args.split_whitespace().collect::<Vec<&str>>();
+    let destination = Destination::parse(&args, source.sender().as_deref())?;
+    // caught here so a typo gets answered right away, not when a bot gets to it
+    if let Anchor::Waypoint(name) = &destination.anchor {
+        if !source.state.config.read().waypoints.contains_key(name) {
+            return Err(format!("There's no waypoint called {}", name));
+        }
+    }
+    Ok(Box::new(GotoBlock::to(destination)))
 }
 
 impl BotTask for GotoBlock {
     fn get_name(&self) -> &str {
         "Goto"
     }
-    fn on_event(&mut self, bot: &Client, event: &Event) {
-        if !self.started {
-            self.started = true;
-            bot.chat(
-                format!("Going to ({}, {}, {})", self.x, self.y.unwrap_or(0), self.z).as_str(),
-            );
-            if let Some(y) = self.y {
-                bot.start_goto(goals::BlockPosGoal(BlockPos {
-                    x: self.x,
-                    y,
-                    z: self.z,
-                }));
-            } else {
-                bot.start_goto(goals::XZGoal {
-                    x: self.x,
-                    z: self.z,
-                });
+    fn on_start(&mut self, bot: &Client) {
+        // Resolved here rather than when queued, so ~ is wherever this bot is now
+        let target = match self.destination.resolve(bot) {
+            Ok(target) => target,
+            Err(reason) => {
+                self.failure = Some(reason);
+                return;
             }
+        };
+        self.target = Some(target);
+        self.start_distance = Some(Self::distance_from(&target, bot));
+        self.started_at = Some(Instant::now());
+        // e.g. "Going to @Steve ^ ^ ^5 (12 64 -30)", or just the block if
+        // that's what was asked for
+        let (destination, block) = (self.destination.to_string(), target.to_string());
+        if destination == block {
+            metrics::chat(bot, format!("Going to {}", block).as_str());
+        } else {
+            metrics::chat(
+                bot,
+                format!("Going to {} ({})", destination, block).as_str(),
+            );
+        }
+        if let Some(y) = target.y {
+            bot.start_goto(goals::BlockPosGoal(BlockPos {
+                x: target.x,
+                y,
+                z: target.z,
+            }));
         } else {
-            match event {
-                Event::Tick => {
-                    self.finished = {
-                        let pos = bot.position().to_block_pos_floor();
+            bot.start_goto(goals::XZGoal {
+                x: target.x,
+                z: target.z,
+            });
+        }
+    }
+    fn on_tick(&mut self, bot: &Client) {
+        let Some(target) = self.target else {
+            return;
+        };
+        self.remaining = Some(Self::distance_from(&target, bot));
+        self.finished = {
+            let pos = bot.position().to_block_pos_floor();
 
-                        pos.x == self.x
-                            && pos.z == self.z
-                            && (self.y.is_none() || pos.y == self.y.unwrap())
-                    }
-                }
-                _ => {}
+            pos.x == target.x && pos.z == target.z && target.y.is_none_or(|y| pos.y == y)
+        };
+        if self.finished {
+            if let Some(started_at) = self.started_at {
+                bot.resource::<SwarmState>()
+                    .metrics
+                    .pathfinding_took(started_at.elapsed());
             }
         }
     }
 
-    fn end(&self) -> bool {
-        // bot.chat("Arrived!")
-        self.finished
+    fn on_cancel(&mut self, bot: &Client) {
+        bot.stop_pathfinding();
+        bot.walk(WalkDirection::None);
+    }
+
+    fn end(&self) -> Option<TaskOutcome> {
+        if let Some(reason) = &self.failure {
+            return Some(TaskOutcome::Failed(reason.clone()));
+        }
+        self.finished.then_some(TaskOutcome::Success)
+    }
+
+    fn default_limits(&self) -> TaskLimits {
+        TaskLimits {
+            timeout: Some(TIMEOUT),
+            stall_ticks: Some(STALL_TICKS),
+        }
+    }
+
+    fn progress(&self) -> Option<TaskProgress> {
+        let remaining = self.remaining.or(self.start_distance)?;
+        let fraction = self
+            .start_distance
+            .filter(|start| *start > 0.0)
+            .map(|start| (1.0 - remaining / start) as f32);
+        Some(TaskProgress::new(
+            fraction,
+            format!("{:.0} blocks remaining to {}", remaining, self.destination),
+        ))
+    }
+
+    fn snapshot(&self) -> TaskSnapshot {
+        // Once resolved it's saved as the block, so a resumed goto doesn't
+        // head somewhere else relative to where the bot rejoined
+        match self.target {
+            Some(target) => TaskSnapshot::Goto {
+                x: target.x,
+                y: target.y,
+                z: target.z,
+            },
+            None => TaskSnapshot::GotoDestination {
+                destination: self.destination.clone(),
+            },
+        }
     }
 }
@@ -1,8 +1,48 @@
 // pub mod combat;
 // pub mod debug;
 // pub mod movement;
+pub mod async_task;
 pub mod chat_task;
+pub mod composite;
+pub mod delay;
+pub mod destination;
+pub mod fetch;
 pub mod goto_block;
+pub mod registry;
 
+pub use async_task::{AsyncTask, TaskContext};
 pub use chat_task::Chat;
+pub use composite::{Parallel, Repeat, Sequence, WaitFor};
+pub use delay::Delay;
+pub use fetch::fetch;
 pub use goto_block::GotoBlock;
+
+use azalea::brigadier::prelude::*;
+use parking_lot::Mutex;
+use registry::TaskSpec;
+
+use crate::commands::CommandSource;
+
+/// Every task type, in the order `!help` lists them.
+pub static TASKS: [&TaskSpec; 8] = [
+    &chat_task::SPEC,
+    &goto_block::SPEC,
+    &fetch::SPEC,
+    &delay::SPEC,
+    &composite::SEQUENCE,
+    &composite::PARALLEL,
+    &composite::RACE,
+    &composite::REPEAT,
+];
+
+/// The task type called `name` or with it as an alias.
+pub fn find(name: &str) -> Option<&'static TaskSpec> {
+    TASKS.iter().copied().find(|spec| spec.is_called(name))
+}
+
+/// Registers the chat syntax of every task.
+pub fn register(commands: &mut CommandDispatcher<Mutex<CommandSource>>) {
+    for spec in TASKS {
+        spec.register(commands);
+    }
+}
@@ -0,0 +1,154 @@
+//! Task types described once, by name, aliases, the arguments they take and
+//! a line of help. The commands for chat, the console and the HTTP API are
+//! all generated from these, and so is `!help <task>`.
+
+use azalea::brigadier::{builder::argument_builder::ArgumentBuilder, prelude::*};
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::{
+    command_controler::BotTask,
+    commands::{CommandSource, Ctx},
+};
+
+/// One argument of a task, read back in `build` by its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "name", rename_all = "snake_case")]
+pub enum Arg {
+    /// A fixed word, like `forever` in `repeat forever <task>`.
+    Literal(&'static str),
+    Integer(&'static str),
+    Float(&'static str),
+    /// The rest of the command.
+    Greedy(&'static str),
+}
+
+impl Arg {
+    pub fn usage(&self) -> String {
+        match self {
+            Arg::Literal(name) => name.to_string(),
+            Arg::Integer(name) | Arg::Float(name) => format!("<{}>", name),
+            Arg::Greedy(name) => format!("<{}...>", name),
+        }
+    }
+
+    fn node(&self) -> ArgumentBuilder<Mutex<CommandSource>> {
+        match *self {
+            Arg::Literal(name) => literal(name),
+            Arg::Integer(name) => argument(name, integer()),
+            Arg::Float(name) => argument(name, float()),
+            Arg::Greedy(name) => argument(name, greedy_string()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TaskSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    /// Every form the arguments can take, e.g. `<x> <z>` and `<x> <y> <z>`.
+    pub forms: &'static [&'static [Arg]],
+    /// What a trailing [`Arg::Greedy`] the task parses itself accepts, e.g.
+    /// `<x> <z>` and `@<player>`. Empty if any text goes.
+    pub greedy_forms: &'static [&'static str],
+    pub help: &'static str,
+    /// Builds the task from a parsed command, or says what's wrong with it.
+    #[serde(skip)]
+    pub build: fn(&Ctx, &CommandSource) -> Result<Box<dyn BotTask>, String>,
+}
+
+impl TaskSpec {
+    /// Registers the command under its name and every alias.
+    pub fn register(&'static self, commands: &mut CommandDispatcher<Mutex<CommandSource>>) {
+        for name in std::iter::once(&self.name).chain(self.aliases) {
+            commands.register(self.tree(literal(name), self.forms));
+        }
+    }
+
+    /// Adds the forms under `node`, sharing the arguments they start with.
+    fn tree(
+        &'static self,
+        mut node: ArgumentBuilder<Mutex<CommandSource>>,
+        forms: &[&'static [Arg]],
+    ) -> ArgumentBuilder<Mutex<CommandSource>> {
+        if forms.iter().any(|form| form.is_empty()) {
+            let build = self.build;
+            node = node.executes(move |ctx: &Ctx| {
+                let source = ctx.source.lock();
+                match build(ctx, &source) {
+                    Ok(task) => match source.enqueue(task) {
+                        Ok(()) => 1,
+                        Err(err) => {
+                            source.reply(err.as_str());
+                            0
+                        }
+                    },
+                    Err(err) => {
+                        source.reply(err.as_str());
+                        for usage in self.usages() {
+                            source.reply(format!("Usage: {}", usage).as_str());
+                        }
+                        0
+                    }
+                }
+            });
+        }
+
+        let mut firsts = Vec::<Arg>::new();
+        for first in forms.iter().filter_map(|form| form.first()) {
+            if !firsts.contains(first) {
+                firsts.push(*first);
+            }
+        }
+        for first in firsts {
+            let rest = forms
+                .iter()
+                .filter(|form| form.first() == Some(&first))
+                .map(|form| &form[1..])
+                .collect::<Vec<&'static [Arg]>>();
+            node = node.then(self.tree(first.node(), &rest));
+        }
+        node
+    }
+
+    /// `!goto <x> <z>` and so on, one per form, with the greedy forms
+    /// spelled out.
+    pub fn usages(&self) -> Vec<String> {
+        let mut usages = Vec::new();
+        for form in self.forms {
+            let words = std::iter::once(format!("!{}", self.name))
+                .chain(form.iter().map(Arg::usage))
+                .collect::<Vec<String>>();
+            match form.last() {
+                Some(Arg::Greedy(_)) if !self.greedy_forms.is_empty() => {
+                    let start = words[..words.len() - 1].join(" ");
+                    usages.extend(
+                        self.greedy_forms
+                            .iter()
+                            .map(|greedy_form| format!("{} {}", start, greedy_form)),
+                    );
+                }
+                _ => usages.push(words.join(" ")),
+            }
+        }
+        usages
+    }
+
+    /// What `!help <task>` replies with.
+    pub fn help_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("{}: {}", self.name, self.help)];
+        lines.extend(
+            self.usages()
+                .into_iter()
+                .map(|usage| format!("Usage: {}", usage)),
+        );
+        if !self.aliases.is_empty() {
+            lines.push(format!("Also: {}", self.aliases.join(", ")));
+        }
+        lines
+    }
+
+    pub fn is_called(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
@@ -0,0 +1,140 @@
+//! Keeps what the bots say within what a vanilla server accepts: one line of
+//! at most 256 characters, without `§` formatting codes or control
+//! characters. The server disconnects a client that sends anything else.
+
+/// The longest chat message or command a vanilla server accepts, counted in
+/// UTF-16 units like Java does.
+pub static MAX_LENGTH: usize = 256;
+
+/// `text` on one line, without formatting codes or characters the server
+/// would reject.
+pub fn sanitize(text: &str) -> String {
+    let mut clean = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            // the code after it means nothing on its own
+            chars.next();
+        } else if c.is_control() || c.is_whitespace() {
+            clean.push(' ');
+        } else {
+            clean.push(c);
+        }
+    }
+    clean.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Cuts `message` down to [`MAX_LENGTH`], ending it with `...` if it had to.
+pub fn truncate(message: &str) -> String {
+    if length(message) <= MAX_LENGTH {
+        return message.to_string();
+    }
+    format!("{}...", split_at(message, MAX_LENGTH - 3).0)
+}
+
+/// `message` sanitized and broken into as many messages starting with
+/// `prefix`, like `/w Steve `, as it takes to stay under [`MAX_LENGTH`].
+/// Lines are broken between words unless a word is longer than a line.
+pub fn split(prefix: &str, message: &str) -> Vec<String> {
+    let room = MAX_LENGTH.saturating_sub(length(prefix)).max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in sanitize(message).split(' ') {
+        if !line.is_empty() && length(line.as_str()) + 1 + length(word) <= room {
+            line.push(' ');
+            line.push_str(word);
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        let mut rest = word;
+        while length(rest) > room {
+            let (start, end) = split_at(rest, room);
+            lines.push(start.to_string());
+            rest = end;
+        }
+        line = rest.to_string();
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+        .into_iter()
+        .map(|line| format!("{}{}", prefix, line))
+        .collect()
+}
+
+fn length(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Splits `text` after as many characters as fit in `units`.
+fn split_at(text: &str, units: usize) -> (&str, &str) {
+    let mut length = 0;
+    for (index, c) in text.char_indices() {
+        length += c.len_utf16();
+        if length > units {
+            return text.split_at(index);
+        }
+    }
+    (text, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattens_kick_screens() {
+        assert_eq!(
+            sanitize("§c§lYou are banned!\n\n§7Reason: §fcheating\r\nAppeal at\texample.com"),
+            "You are banned! Reason: cheating Appeal at example.com"
+        );
+        assert_eq!(sanitize("a\u{0}b\u{7f}c"), "a b c");
+        assert_eq!(sanitize("trailing §"), "trailing");
+        assert_eq!(sanitize("  "), "");
+    }
+
+    #[test]
+    fn truncates_to_the_chat_limit() {
+        let exact = "a".repeat(MAX_LENGTH);
+        assert_eq!(truncate(exact.as_str()), exact);
+
+        let long = truncate("a".repeat(1000).as_str());
+        assert_eq!(long.len(), MAX_LENGTH);
+        assert!(long.ends_with("..."));
+
+        // counted like Java, where this takes two units
+        let wide = truncate("😀".repeat(200).as_str());
+        assert!(wide.encode_utf16().count() <= MAX_LENGTH);
+    }
+
+    #[test]
+    fn splits_between_words() {
+        let words = ["word"; 100].join(" ");
+        let lines = split("/w Steve ", words.as_str());
+        assert_eq!(lines.len(), 3);
+        for line in &lines {
+            assert!(line.len() <= MAX_LENGTH);
+            assert!(line.starts_with("/w Steve word"));
+            assert!(line.ends_with("word"));
+        }
+        assert_eq!(
+            lines
+                .iter()
+                .map(|line| line.trim_start_matches("/w Steve "))
+                .collect::<Vec<&str>>()
+                .join(" "),
+            words
+        );
+
+        let long = split("", "a".repeat(600).as_str());
+        assert_eq!(
+            long.iter().map(String::len).collect::<Vec<usize>>(),
+            [256, 256, 88]
+        );
+        assert_eq!(split("", "one\ntwo"), ["one two"]);
+        assert!(split("", "").is_empty());
+    }
+}
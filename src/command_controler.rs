@@ -1,10 +1,132 @@
-use azalea::{Client, Event};
-use std::fmt::Debug;
+use azalea::{Client, Event, chat::ChatPacket};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Debug, Display},
+    time::{Duration, Instant},
+};
+use tracing::{Span, info, info_span, warn};
 
-pub trait BotTask: Send {
+use crate::{
+    SwarmState,
+    persistence::{QueuedTaskSnapshot, TaskSnapshot},
+    task_plugin::Motion,
+};
+
+/// Tasks a bot was interrupted in come back ahead of everything else in the
+/// queue.
+pub static RESUME_PRIORITY: i32 = i32::MAX;
+
+/// How a task ended, handed back to the swarm once `end` reports it is done.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "result", content = "reason", rename_all = "snake_case")]
+pub enum TaskOutcome {
+    Success,
+    Failed(String),
+    Cancelled,
+}
+
+impl Display for TaskOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskOutcome::Success => write!(f, "succeeded"),
+            TaskOutcome::Failed(reason) => write!(f, "failed: {}", reason),
+            TaskOutcome::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+pub trait BotTask: Send + BotTaskClone {
     fn get_name(&self) -> &str;
-    fn on_event(&mut self, bot: &Client, event: &Event);
-    fn end(&self) -> bool;
+    /// Called once when a bot picks the task up, before the first tick.
+    fn on_start(&mut self, _bot: &Client) {}
+    /// Called once per game tick until the task ends.
+    fn on_tick(&mut self, bot: &Client);
+    /// Called for the events between ticks a task may want to react to.
+    fn on_event(&mut self, _bot: &Client, _event: &TaskEvent) {}
+    /// Called when the task is aborted before it ended on its own.
+    fn on_cancel(&mut self, _bot: &Client) {}
+    /// `None` while the task is still running, the outcome once it is done.
+    fn end(&self) -> Option<TaskOutcome>;
+    /// Deadline and stall detection for this task type, before any overrides
+    /// from the config or the command line.
+    fn default_limits(&self) -> TaskLimits {
+        TaskLimits::default()
+    }
+    /// The task and how far it got, so it can be resumed after a restart.
+    fn snapshot(&self) -> TaskSnapshot;
+    /// How far along the task is, for status reports. `None` if the task
+    /// can't tell.
+    fn progress(&self) -> Option<TaskProgress> {
+        None
+    }
+}
+
+/// The events tasks get delivered, out of everything the bot receives.
+#[derive(Debug, Clone)]
+pub enum TaskEvent {
+    Chat(ChatPacket),
+    Death,
+}
+
+impl TaskEvent {
+    /// `None` for ticks and for events no task cares about.
+    pub fn from_event(event: &Event) -> Option<Self> {
+        match event {
+            Event::Chat(packet) => Some(TaskEvent::Chat(packet.clone())),
+            Event::Death(_) => Some(TaskEvent::Death),
+            _ => None,
+        }
+    }
+}
+
+/// How far along a running task is.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskProgress {
+    /// From 0 to 1, if the task knows how much is left.
+    pub fraction: Option<f32>,
+    /// What the task is doing, e.g. "42 blocks remaining".
+    pub stage: String,
+}
+
+impl TaskProgress {
+    pub fn new(fraction: Option<f32>, stage: impl Into<String>) -> Self {
+        Self {
+            fraction: fraction.map(|fraction| fraction.clamp(0.0, 1.0)),
+            stage: stage.into(),
+        }
+    }
+
+    /// `done` out of `total`, e.g. "3/7 messages".
+    pub fn count(done: usize, total: usize, unit: &str) -> Self {
+        let fraction = (total > 0).then(|| done as f32 / total as f32);
+        Self::new(fraction, format!("{}/{} {}", done, total, unit))
+    }
+}
+
+impl Display for TaskProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.fraction {
+            Some(fraction) => write!(f, "{} ({:.0}%)", self.stage, fraction * 100.0),
+            None => write!(f, "{}", self.stage),
+        }
+    }
+}
+
+/// Lets a queued task be handed to several bots, see [`TaskTarget`].
+pub trait BotTaskClone {
+    fn clone_box(&self) -> Box<dyn BotTask>;
+}
+
+impl<T: BotTask + Clone + 'static> BotTaskClone for T {
+    fn clone_box(&self) -> Box<dyn BotTask> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn BotTask> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
 }
 
 impl Debug for dyn BotTask {
@@ -12,3 +134,436 @@ impl Debug for dyn BotTask {
         write!(f, "BotTask({})", self.get_name())
     }
 }
+
+/// Limits the runner holds a task to on top of its own end condition.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskLimits {
+    /// Fail the task if it hasn't ended this long after starting.
+    pub timeout: Option<Duration>,
+    /// Fail the task once the bot hasn't moved for this many ticks while the
+    /// pathfinder is neither calculating nor executing a path.
+    pub stall_ticks: Option<u32>,
+}
+
+/// Which bots may pick up a queued task.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskTarget {
+    /// Whichever bot goes idle first.
+    #[default]
+    Any,
+    /// Only the bot with this username.
+    Bot(String),
+    /// This many different bots, each running its own copy.
+    Bots(usize),
+    /// One copy for every connected bot.
+    All,
+}
+
+impl TaskTarget {
+    pub fn parse(arg: &str) -> Result<Self, String> {
+        match arg {
+            "any" => Ok(TaskTarget::Any),
+            "all" => Ok(TaskTarget::All),
+            _ => match arg.parse::<usize>() {
+                Ok(0) => Err("A task needs at least one bot".to_string()),
+                Ok(count) => Ok(TaskTarget::Bots(count)),
+                Err(_) => Ok(TaskTarget::Bot(arg.to_string())),
+            },
+        }
+    }
+}
+
+/// The way it's written in `to=`.
+impl Display for TaskTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskTarget::Any => write!(f, "any"),
+            TaskTarget::Bot(username) => write!(f, "{}", username),
+            TaskTarget::Bots(count) => write!(f, "{}", count),
+            TaskTarget::All => write!(f, "all"),
+        }
+    }
+}
+
+/// `key=value` options given alongside a task's own arguments, e.g.
+/// `!goto timeout=60 stall=40 priority=5 to=bot1 10 64 10`.
+#[derive(Debug, Clone, Default)]
+pub struct TaskOptions {
+    pub timeout: Option<Duration>,
+    pub stall_ticks: Option<u32>,
+    pub priority: Option<i32>,
+    pub target: Option<TaskTarget>,
+}
+
+impl TaskOptions {
+    /// Removes the options from the front of `args`, before and right after
+    /// the command's name, leaving the command and its own arguments behind.
+    /// Anything from the first argument on is left alone, so the steps of a
+    /// composite task and chat messages can have `=` in them.
+    pub fn extract(args: &mut Vec<String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut rest = Vec::new();
+        let mut args_started = false;
+        for arg in args.drain(..) {
+            if args_started || !options.parse(arg.as_str())? {
+                // the first word that isn't an option is the command's name
+                args_started = !rest.is_empty();
+                rest.push(arg);
+            }
+        }
+        *args = rest;
+        Ok(options)
+    }
+
+    /// Sets the option `arg` is, returning false if it isn't one.
+    fn parse(&mut self, arg: &str) -> Result<bool, String> {
+        match arg.split_once('=') {
+            Some(("timeout", value)) => {
+                let secs = value
+                    .parse()
+                    .map_err(|_| format!("Invalid timeout: {}", value))?;
+                self.timeout = Some(Duration::from_secs(secs));
+            }
+            Some(("stall", value)) => {
+                let ticks = value
+                    .parse()
+                    .map_err(|_| format!("Invalid stall ticks: {}", value))?;
+                self.stall_ticks = Some(ticks);
+            }
+            Some(("priority" | "prio", value)) => {
+                let priority = value
+                    .parse()
+                    .map_err(|_| format!("Invalid priority: {}", value))?;
+                self.priority = Some(priority);
+            }
+            Some(("to", value)) => {
+                self.target = Some(TaskTarget::parse(value)?);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Applies the overrides to a task type's limits. Zero disables a limit.
+    pub fn override_limits(&self, mut limits: TaskLimits) -> TaskLimits {
+        if let Some(timeout) = self.timeout {
+            limits.timeout = (!timeout.is_zero()).then_some(timeout);
+        }
+        if let Some(stall_ticks) = self.stall_ticks {
+            limits.stall_ticks = (stall_ticks != 0).then_some(stall_ticks);
+        }
+        limits
+    }
+}
+
+/// A task waiting in the swarm queue for a bot to pick it up.
+#[derive(Debug)]
+pub struct QueuedTask {
+    pub task: Box<dyn BotTask>,
+    pub limits: TaskLimits,
+    /// Higher priorities are handed out first, equal ones in queue order.
+    pub priority: i32,
+    pub target: TaskTarget,
+    /// Bots that already took a copy of a [`TaskTarget::Bots`] task.
+    taken_by: Vec<String>,
+}
+
+impl QueuedTask {
+    pub fn new(task: Box<dyn BotTask>, limits: TaskLimits, options: &TaskOptions) -> Self {
+        Self {
+            task,
+            limits: options.override_limits(limits),
+            priority: options.priority.unwrap_or_default(),
+            target: options.target.clone().unwrap_or_default(),
+            taken_by: Vec::new(),
+        }
+    }
+
+    /// A fresh copy of this task reserved for one bot.
+    pub fn for_bot(&self, username: &str) -> Self {
+        Self {
+            task: self.task.clone(),
+            limits: self.limits,
+            priority: self.priority,
+            target: TaskTarget::Bot(username.to_string()),
+            taken_by: Vec::new(),
+        }
+    }
+
+    pub fn snapshot(&self) -> QueuedTaskSnapshot {
+        QueuedTaskSnapshot {
+            task: self.task.snapshot(),
+            limits: self.limits,
+            priority: self.priority,
+            target: self.target.clone(),
+            taken_by: self.taken_by.clone(),
+        }
+    }
+
+    pub fn restore(snapshot: QueuedTaskSnapshot) -> Self {
+        Self {
+            task: snapshot.task.restore(),
+            limits: snapshot.limits,
+            priority: snapshot.priority,
+            target: snapshot.target,
+            taken_by: snapshot.taken_by,
+        }
+    }
+
+    pub fn accepts(&self, username: &str) -> bool {
+        match &self.target {
+            TaskTarget::Any | TaskTarget::All => true,
+            TaskTarget::Bot(name) => name == username,
+            TaskTarget::Bots(_) => !self.taken_by.iter().any(|name| name == username),
+        }
+    }
+}
+
+/// Hands out the highest priority task `username` is allowed to run. Tasks
+/// meant for several bots stay queued until enough bots took a copy.
+pub fn take_task(queue: &mut Vec<QueuedTask>, username: &str) -> Option<QueuedTask> {
+    let index = queue.iter().position(|queued| queued.accepts(username))?;
+    let queued = &mut queue[index];
+    if let TaskTarget::Bots(count) = queued.target {
+        queued.taken_by.push(username.to_string());
+        if queued.taken_by.len() < count {
+            return Some(queued.for_bot(username));
+        }
+    }
+    Some(queue.remove(index))
+}
+
+/// Queues a task behind everything with the same or a higher priority.
+pub fn insert_task(queue: &mut Vec<QueuedTask>, task: QueuedTask) {
+    let index = queue
+        .iter()
+        .position(|queued| queued.priority < task.priority)
+        .unwrap_or(queue.len());
+    queue.insert(index, task);
+}
+
+/// A task a bot is working on, checked against its limits every tick.
+pub struct RunningTask {
+    task: Box<dyn BotTask>,
+    limits: TaskLimits,
+    started_at: Instant,
+    /// Ticks the task has run for.
+    ticks: u32,
+    failure: Option<TaskOutcome>,
+    /// Everything the task logs is inside this, nested in its bot's span or
+    /// its parent task's.
+    span: Span,
+}
+
+impl RunningTask {
+    pub fn start(mut task: Box<dyn BotTask>, limits: TaskLimits, bot: &Client) -> Self {
+        let span = info_span!("task", task = task.get_name());
+        span.in_scope(|| {
+            info!(?limits, "Starting");
+            task.on_start(bot);
+        });
+        Self {
+            task,
+            limits,
+            started_at: Instant::now(),
+            ticks: 0,
+            failure: None,
+            span,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.task.get_name()
+    }
+
+    pub fn limits(&self) -> TaskLimits {
+        self.limits
+    }
+
+    pub fn snapshot(&self) -> TaskSnapshot {
+        self.task.snapshot()
+    }
+
+    pub fn progress(&self) -> Option<TaskProgress> {
+        self.task.progress()
+    }
+
+    /// The task with its progress so far, to be queued again for `target`
+    /// after the bot running it was interrupted.
+    pub fn requeue(&self, target: TaskTarget) -> QueuedTask {
+        QueuedTask {
+            task: self.snapshot().restore(),
+            limits: self.limits,
+            priority: RESUME_PRIORITY,
+            target,
+            taken_by: Vec::new(),
+        }
+    }
+
+    /// The task's name and progress, e.g. "Chat: 3/7 messages (43%)".
+    pub fn describe(&self) -> String {
+        match self.progress() {
+            Some(progress) => format!("{}: {}", self.get_name(), progress),
+            None => self.get_name().to_string(),
+        }
+    }
+
+    /// Starts a child of a composite task with its type's limits from the
+    /// config, as if it had been queued on its own.
+    pub fn start_child(task: &dyn BotTask, bot: &Client) -> Self {
+        let limits = bot.resource::<SwarmState>().config.read().task_limits(task);
+        Self::start(task.clone_box(), limits, bot)
+    }
+
+    /// Returns the outcome if the task is done, otherwise runs a tick of it.
+    pub fn tick(&mut self, bot: &Client) -> Option<TaskOutcome> {
+        let outcome = self.end();
+        if outcome.is_some() {
+            return outcome;
+        }
+        let _span = self.span.clone().entered();
+        self.ticks += 1;
+        self.task.on_tick(bot);
+        if let Some(reason) = self.check_limits(bot) {
+            warn!(%reason, "Stopping the task");
+            self.task.on_cancel(bot);
+            self.failure = Some(TaskOutcome::Failed(reason));
+        }
+        None
+    }
+
+    /// Delivers the event unless the task is already done.
+    pub fn deliver(&mut self, bot: &Client, event: &TaskEvent) {
+        if self.end().is_none() {
+            let _span = self.span.clone().entered();
+            self.task.on_event(bot, event);
+        }
+    }
+
+    pub fn cancel(&mut self, bot: &Client) {
+        let _span = self.span.enter();
+        info!("Cancelled");
+        self.task.on_cancel(bot);
+    }
+
+    /// The task's own outcome, or the failure from a limit it broke.
+    pub fn end(&self) -> Option<TaskOutcome> {
+        self.failure.clone().or_else(|| self.task.end())
+    }
+
+    fn check_limits(&mut self, bot: &Client) -> Option<String> {
+        if let Some(timeout) = self.limits.timeout {
+            if self.started_at.elapsed() >= timeout {
+                return Some(format!("timed out after {}s", timeout.as_secs()));
+            }
+        }
+
+        let stall_ticks = self.limits.stall_ticks?;
+        // the bot may have been standing still since before this task started
+        let still_ticks = bot.get_component::<Motion>()?.still_ticks.min(self.ticks);
+        (still_ticks >= stall_ticks).then(|| format!("stalled for {} ticks", still_ticks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot_task::Chat;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split(' ').map(str::to_string).collect()
+    }
+
+    /// A task told apart from the others by the message it would send.
+    fn queued(message: &str, priority: i32, target: TaskTarget) -> QueuedTask {
+        let options = TaskOptions {
+            priority: Some(priority),
+            target: Some(target),
+            ..TaskOptions::default()
+        };
+        QueuedTask::new(
+            Box::new(Chat::init(vec![message.to_string()])),
+            TaskLimits::default(),
+            &options,
+        )
+    }
+
+    fn message(queued: &QueuedTask) -> String {
+        match queued.task.snapshot() {
+            TaskSnapshot::Chat { messages, .. } => messages.concat(),
+            snapshot => panic!("not a chat task: {:?}", snapshot),
+        }
+    }
+
+    #[test]
+    fn extracts_options_around_the_command_name() {
+        let mut line = args("priority=5 goto to=bot1 timeout=60 10 64 10");
+        let options = TaskOptions::extract(&mut line).unwrap();
+        assert_eq!(line, args("goto 10 64 10"));
+        assert_eq!(options.priority, Some(5));
+        assert_eq!(options.target, Some(TaskTarget::Bot("bot1".to_string())));
+        assert_eq!(options.timeout, Some(Duration::from_secs(60)));
+
+        // past the first argument they belong to the command
+        let mut line = args("chat hello to=everyone stall=5");
+        let options = TaskOptions::extract(&mut line).unwrap();
+        assert_eq!(line, args("chat hello to=everyone stall=5"));
+        assert_eq!(options.target, None);
+        assert_eq!(options.stall_ticks, None);
+
+        assert!(TaskOptions::extract(&mut args("goto timeout=soon 1 2 3")).is_err());
+    }
+
+    #[test]
+    fn parses_targets() {
+        assert_eq!(TaskTarget::parse("any"), Ok(TaskTarget::Any));
+        assert_eq!(TaskTarget::parse("all"), Ok(TaskTarget::All));
+        assert_eq!(TaskTarget::parse("3"), Ok(TaskTarget::Bots(3)));
+        assert_eq!(
+            TaskTarget::parse("bot1"),
+            Ok(TaskTarget::Bot("bot1".to_string()))
+        );
+        assert!(TaskTarget::parse("0").is_err());
+        assert!(TaskOptions::extract(&mut args("to=0 goto 1 2 3")).is_err());
+    }
+
+    #[test]
+    fn queues_by_priority_then_in_order() {
+        let mut queue = Vec::new();
+        for (message, priority) in [("a", 0), ("b", 5), ("c", 0), ("d", 5), ("e", -1)] {
+            insert_task(&mut queue, queued(message, priority, TaskTarget::Any));
+        }
+        assert_eq!(
+            queue.iter().map(message).collect::<Vec<String>>(),
+            ["b", "d", "a", "c", "e"]
+        );
+    }
+
+    #[test]
+    fn hands_a_copy_to_each_of_several_bots() {
+        let mut queue = vec![
+            queued("two", 0, TaskTarget::Bots(2)),
+            queued("other", 0, TaskTarget::Bot("bot2".to_string())),
+        ];
+
+        let first = take_task(&mut queue, "bot0").unwrap();
+        assert_eq!(message(&first), "two");
+        assert_eq!(first.target, TaskTarget::Bot("bot0".to_string()));
+        assert_eq!(queue.len(), 2);
+
+        // a bot only ever gets one copy
+        assert!(take_task(&mut queue, "bot0").is_none());
+
+        let second = take_task(&mut queue, "bot1").unwrap();
+        assert_eq!(message(&second), "two");
+        assert_eq!(second.target, TaskTarget::Bots(2));
+        assert_eq!(
+            queue.iter().map(message).collect::<Vec<String>>(),
+            ["other"]
+        );
+
+        assert!(take_task(&mut queue, "bot1").is_none());
+        assert_eq!(message(&take_task(&mut queue, "bot2").unwrap()), "other");
+        assert!(queue.is_empty());
+    }
+}
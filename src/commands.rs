@@ -0,0 +1,499 @@
+use std::sync::Arc;
+
+use azalea::{
+    brigadier::{context::CommandContext, prelude::*},
+    chat::ChatPacket,
+    swarm::Swarm,
+};
+use parking_lot::Mutex;
+use tokio::runtime::Handle;
+use tracing::{error, info};
+
+use crate::{
+    NotCancelled, SwarmState, bot_task, cancel_all, cancel_bot, chat,
+    command_controler::{BotTask, QueuedTask, TaskOptions},
+    metrics,
+    permissions::Denial,
+    reload,
+    status::SwarmStatus,
+};
+
+pub type Ctx = CommandContext<Mutex<CommandSource>>;
+
+/// What the commands that aren't tasks do, for `!help`. Tasks describe
+/// themselves in their [`TaskSpec`](bot_task::registry::TaskSpec), and which
+/// commands there are is read from the dispatcher.
+static DESCRIPTIONS: [(&str, &str); 6] = [
+    (
+        "status",
+        "Shows what every bot is doing and the queue, a page at a time, or as a table or JSON outside chat.",
+    ),
+    (
+        "cancel",
+        "Cancels the task one bot or every bot is running.",
+    ),
+    ("clear", "Drops every queued task."),
+    ("remove", "Drops a queued task by its place in the queue."),
+    ("reload", "Reads the config file again."),
+    ("help", "Lists the commands, or explains one."),
+];
+
+/// Where a command came from, which decides how it's authorized and where
+/// its replies go.
+#[derive(Clone)]
+pub enum Origin {
+    /// A `!` message in game chat, checked against the sender's role.
+    Chat(ChatPacket),
+    /// A request to the HTTP API, which checked its token already. Replies
+    /// are collected to send back in the response.
+    Api(Arc<Mutex<Vec<String>>>),
+    /// A line typed into the terminal the swarm runs in. Replies are only
+    /// printed.
+    Console,
+}
+
+/// Who sent a command and where the tasks it builds should go.
+#[derive(Clone)]
+pub struct CommandSource {
+    pub swarm: Swarm,
+    pub state: SwarmState,
+    pub origin: Origin,
+    pub options: TaskOptions,
+    /// Set while parsing the steps of a composite task, so the tasks they
+    /// build are collected here instead of being queued.
+    captured: Option<Arc<Mutex<Vec<Box<dyn BotTask>>>>>,
+}
+
+impl CommandSource {
+    pub fn new(swarm: Swarm, state: SwarmState, origin: Origin) -> Self {
+        Self {
+            swarm,
+            state,
+            origin,
+            options: TaskOptions::default(),
+            captured: None,
+        }
+    }
+
+    /// Answers the sender. Chat gets answered through the first connected bot,
+    /// whispering back if the command was whispered, in as many messages as
+    /// it takes to stay under the chat limit.
+    pub fn reply(&self, message: &str) {
+        let chat = match &self.origin {
+            Origin::Chat(chat) => chat,
+            Origin::Api(replies) => {
+                info!(reply = message, "Replying to the HTTP API");
+                replies.lock().push(message.to_string());
+                return;
+            }
+            Origin::Console => {
+                println!("{}", message);
+                return;
+            }
+        };
+        info!(to = ?chat.sender(), reply = message, "Replying in chat");
+        let Some(bot) = self.swarm.clone().into_iter().next() else {
+            return;
+        };
+        let prefix = match chat.sender() {
+            Some(sender) if chat.is_whisper() => format!("/w {} ", sender),
+            _ => String::new(),
+        };
+        for line in chat::split(prefix.as_str(), message) {
+            metrics::chat(&bot, line.as_str());
+        }
+    }
+
+    fn from_chat(&self) -> bool {
+        matches!(self.origin, Origin::Chat(_))
+    }
+
+    /// The player who sent the command, if it came from chat.
+    pub fn sender(&self) -> Option<String> {
+        match &self.origin {
+            Origin::Chat(chat) => chat.sender(),
+            Origin::Api(_) | Origin::Console => None,
+        }
+    }
+
+    /// Queues a task built by a command, with the options it was given, or
+    /// says why it can't be.
+    pub fn enqueue(&self, task: Box<dyn BotTask>) -> Result<(), String> {
+        match &self.captured {
+            Some(captured) => {
+                captured.lock().push(task);
+                Ok(())
+            }
+            None => {
+                let limits = self.state.config.read().task_limits(task.as_ref());
+                self.state
+                    .enqueue(&self.swarm, QueuedTask::new(task, limits, &self.options))
+            }
+        }
+    }
+
+    /// Whether this command is being parsed as a step of a composite task.
+    pub fn is_step(&self) -> bool {
+        self.captured.is_some()
+    }
+
+    /// Parses `goto 0 0 ; chat hi` into one task per `;` separated step.
+    pub fn parse_steps(&self, input: &str) -> Result<Vec<Box<dyn BotTask>>, String> {
+        input.split(';').map(|step| self.parse_step(step)).collect()
+    }
+
+    /// Checks the sender may run `command`, replying with the denial message
+    /// if they can't.
+    fn permitted(&self, command: &str) -> bool {
+        let Origin::Chat(chat) = &self.origin else {
+            return true;
+        };
+        let config = self.state.config.read();
+        match config.permissions.check(chat, command) {
+            Ok(()) => true,
+            Err(Denial::Ignored) => false,
+            Err(Denial::Denied) => {
+                info!(command, sender = ?chat.sender(), "Denied a command");
+                let message = &config.permissions.denial_message;
+                if !message.is_empty() {
+                    self.reply(message.as_str());
+                }
+                false
+            }
+        }
+    }
+
+    /// Parses a single task through the dispatcher without queuing it.
+    pub fn parse_step(&self, input: &str) -> Result<Box<dyn BotTask>, String> {
+        let name = input.split_whitespace().next().unwrap_or_default();
+        if !self.permitted(name) {
+            return Err(format!("Not allowed to use {}", name));
+        }
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let step = Self {
+            options: TaskOptions::default(),
+            captured: Some(captured.clone()),
+            ..self.clone()
+        };
+        self.state
+            .commands
+            .execute(input.trim().to_string(), Mutex::new(step))
+            .map_err(|err| err.message())?;
+        let mut tasks = captured.lock();
+        match tasks.len() {
+            1 => Ok(tasks.remove(0)),
+            _ => Err(format!("\"{}\" is not a task", input.trim())),
+        }
+    }
+
+    /// Takes the task options like `timeout=60` out of `input` and runs the
+    /// rest as a command. A leading `@bot1` is short for `to=bot1`. Returns
+    /// whether it ran.
+    pub fn run(mut self, input: &str) -> bool {
+        let mut args = input
+            .split_whitespace()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>();
+        if let Some(bot) = args.first().and_then(|arg| arg.strip_prefix('@')) {
+            args[0] = format!("to={}", bot);
+        }
+        // before anything is said about the options, so players who may not
+        // run the command can't make the bots repeat their words
+        let name = args.iter().find(|arg| !arg.contains('='));
+        if !self.permitted(name.map_or("", String::as_str)) {
+            return false;
+        }
+        match TaskOptions::extract(&mut args) {
+            Ok(options) => self.options = options,
+            Err(err) => {
+                self.reply(err.as_str());
+                return false;
+            }
+        }
+        self.execute(args.join(" ").as_str())
+    }
+
+    /// Runs a command, replying with the error and the command's usage if it
+    /// doesn't parse. Returns whether it ran.
+    pub fn execute(self, command: &str) -> bool {
+        let name = command.split_whitespace().next().unwrap_or_default();
+        if !self.permitted(name) {
+            return false;
+        }
+        let commands = self.state.commands.clone();
+        match commands.execute(command.to_string(), Mutex::new(self.clone())) {
+            Ok(result) => result > 0,
+            Err(err) => {
+                self.reply(err.message().as_str());
+                let usages = self.usages(name);
+                if usages.is_empty() {
+                    self.reply("Try !help for a list of commands");
+                }
+                for usage in usages {
+                    self.reply(format!("Usage: {}", usage).as_str());
+                }
+                false
+            }
+        }
+    }
+
+    /// The names of every registered command, aliases included, sorted.
+    pub fn command_names(&self) -> Vec<String> {
+        self.state
+            .commands
+            .root
+            .read()
+            .children
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// Every way to call the command `name`, e.g. `!cancel all`, read from
+    /// its definition. Empty if there's no such command.
+    pub fn usages(&self, name: &str) -> Vec<String> {
+        let commands = &self.state.commands;
+        let Some(node) = commands.root.read().child(name) else {
+            return Vec::new();
+        };
+        let usages = commands.get_all_usage(&node.read(), &Mutex::new(self.clone()), true);
+        if usages.is_empty() {
+            // a command without arguments has a single, empty usage
+            return vec![format!("!{}", name)];
+        }
+        usages
+            .into_iter()
+            .map(|usage| format!("!{} {}", name, usage))
+            .collect()
+    }
+}
+
+pub fn register_commands(commands: &mut CommandDispatcher<Mutex<CommandSource>>) {
+    bot_task::register(commands);
+
+    let status_page = |ctx: &Ctx, page: i32| {
+        let source = ctx.source.lock();
+        if not_a_step(&source, "status") {
+            return 0;
+        }
+        let status = SwarmStatus::of(&source.swarm, &source.state);
+        let Some(page) = usize::try_from(page)
+            .ok()
+            .filter(|page| (1..=status.pages()).contains(page))
+        else {
+            source.reply(format!("There are {} status pages", status.pages()).as_str());
+            return 0;
+        };
+        for line in status.page(page) {
+            source.reply(line.as_str());
+        }
+        1
+    };
+    commands.register(
+        literal("status")
+            .executes(move |ctx: &Ctx| status_page(ctx, 1))
+            // the table and the JSON are far too long for chat
+            .then(
+                literal("table")
+                    .requires(|source: &Mutex<CommandSource>| !source.lock().from_chat())
+                    .executes(|ctx: &Ctx| {
+                        let source = ctx.source.lock();
+                        if not_a_step(&source, "status") {
+                            return 0;
+                        }
+                        for line in SwarmStatus::of(&source.swarm, &source.state)
+                            .table()
+                            .lines()
+                        {
+                            source.reply(line);
+                        }
+                        1
+                    }),
+            )
+            .then(
+                literal("json")
+                    .requires(|source: &Mutex<CommandSource>| !source.lock().from_chat())
+                    .executes(|ctx: &Ctx| {
+                        let source = ctx.source.lock();
+                        if not_a_step(&source, "status") {
+                            return 0;
+                        }
+                        let status = SwarmStatus::of(&source.swarm, &source.state);
+                        // a single reply, so the API gets it back as one JSON body
+                        match serde_json::to_string(&status) {
+                            Ok(json) => {
+                                source.reply(json.as_str());
+                                1
+                            }
+                            Err(err) => {
+                                error!("Failed to serialize the status: {}", err);
+                                0
+                            }
+                        }
+                    }),
+            )
+            .then(
+                argument("page", integer())
+                    .executes(move |ctx: &Ctx| status_page(ctx, get_integer(ctx, "page").unwrap())),
+            ),
+    );
+
+    commands.register(
+        literal("help")
+            .executes(|ctx: &Ctx| {
+                let source = ctx.source.lock();
+                if not_a_step(&source, "help") {
+                    return 0;
+                }
+                let (mut tasks, mut others) = (Vec::new(), Vec::new());
+                for name in source.command_names() {
+                    match bot_task::find(name.as_str()) {
+                        // aliases are listed by !help <task>
+                        Some(spec) if spec.name != name => {}
+                        Some(_) => tasks.push(name),
+                        None => others.push(name),
+                    }
+                }
+                source.reply(format!("Tasks: {}", tasks.join(", ")).as_str());
+                source.reply(format!("Other commands: {}", others.join(", ")).as_str());
+                source.reply("!help <command> explains one");
+                1
+            })
+            .then(argument("command", word()).executes(|ctx: &Ctx| {
+                let source = ctx.source.lock();
+                if not_a_step(&source, "help") {
+                    return 0;
+                }
+                let name = get_string(ctx, "command").unwrap();
+                let name = name.trim_start_matches('!');
+                if let Some(spec) = bot_task::find(name) {
+                    for line in spec.help_lines() {
+                        source.reply(line.as_str());
+                    }
+                    return 1;
+                }
+                let usages = source.usages(name);
+                if usages.is_empty() {
+                    source.reply(format!("There's no command called {}", name).as_str());
+                    return 0;
+                }
+                if let Some((_, description)) =
+                    DESCRIPTIONS.iter().find(|(command, _)| *command == name)
+                {
+                    source.reply(format!("{}: {}", name, description).as_str());
+                }
+                for usage in usages {
+                    source.reply(format!("Usage: {}", usage).as_str());
+                }
+                1
+            })),
+    );
+
+    commands.register(
+        literal("cancel")
+            .then(literal("all").executes(|ctx: &Ctx| {
+                let source = ctx.source.lock();
+                if not_a_step(&source, "cancel") {
+                    return 0;
+                }
+                let count = cancel_all(&source.swarm);
+                source.reply(format!("Cancelled {} tasks", count).as_str());
+                1
+            }))
+            .then(argument("bot", word()).executes(|ctx: &Ctx| {
+                let source = ctx.source.lock();
+                if not_a_step(&source, "cancel") {
+                    return 0;
+                }
+                let username = get_string(ctx, "bot").unwrap();
+                let not_cancelled = match cancel_bot(&source.swarm, username.as_str()) {
+                    Ok(()) => {
+                        source.reply(format!("Cancelled {}'s task", username).as_str());
+                        return 1;
+                    }
+                    Err(NotCancelled::NoSuchBot) => format!("There's no bot called {}", username),
+                    Err(NotCancelled::Idle) => format!("{} has no task", username),
+                };
+                source.reply(not_cancelled.as_str());
+                0
+            })),
+    );
+
+    commands.register(literal("reload").executes(|ctx: &Ctx| {
+        let source = ctx.source.lock().clone();
+        if not_a_step(&source, "reload") {
+            return 0;
+        }
+        let reloaded = match source.origin {
+            // handling chat shouldn't wait on new accounts logging in
+            Origin::Chat(_) => {
+                tokio::spawn(reload_config(source));
+                true
+            }
+            // the API answers with the replies, so they have to be in first
+            Origin::Api(_) => {
+                tokio::task::block_in_place(|| Handle::current().block_on(reload_config(source)))
+            }
+            // the console reads commands on a blocking thread of its own
+            Origin::Console => Handle::current().block_on(reload_config(source)),
+        };
+        i32::from(reloaded)
+    }));
+
+    commands.register(literal("clear").executes(|ctx: &Ctx| {
+        let source = ctx.source.lock();
+        if not_a_step(&source, "clear") {
+            return 0;
+        }
+        let count = source.state.clear_queue();
+        source.reply(format!("Cleared {} queued tasks", count).as_str());
+        1
+    }));
+
+    commands.register(
+        literal("remove").then(argument("index", integer()).executes(|ctx: &Ctx| {
+            let source = ctx.source.lock();
+            if not_a_step(&source, "remove") {
+                return 0;
+            }
+            let index = get_integer(ctx, "index").unwrap();
+            match usize::try_from(index)
+                .ok()
+                .and_then(|index| source.state.remove_queued(index))
+            {
+                Some(task) => {
+                    source
+                        .reply(format!("Removed {} from the queue", task.task.get_name()).as_str());
+                    1
+                }
+                None => {
+                    source.reply(format!("No queued task {}", index).as_str());
+                    0
+                }
+            }
+        })),
+    );
+}
+
+/// Commands that act on the swarm straight away can't be steps of a composite
+/// task, since parsing the steps would already run them.
+fn not_a_step(source: &CommandSource, name: &str) -> bool {
+    if source.is_step() {
+        source.reply(format!("{} can't be used as a step", name).as_str());
+    }
+    source.is_step()
+}
+
+/// Reloads the config and says how it went, returning whether it worked.
+async fn reload_config(source: CommandSource) -> bool {
+    match reload::reload(&source.swarm, &source.state).await {
+        Ok(()) => {
+            source.reply("Reloaded the config");
+            true
+        }
+        Err(err) => {
+            source.reply(format!("Failed to reload the config: {}", err).as_str());
+            false
+        }
+    }
+}
@@ -0,0 +1,709 @@
+//! Swarm settings, read from `config.toml` and overridden from the command
+//! line.
+//!
+//! ### Arguments
+//!
+//! - `--config` or `-C`: The config file to read, `config.toml` by default.
+//! - `--owner` or `-O`: A player that gets the admin role. Can be repeated.
+//! - `--account` or `-A`: Comma separated usernames or emails of the bots,
+//!   replacing the accounts from the config.
+//! - `--server` or `-S`: The address of the server to join.
+//! - `--join-delay` or `-J`: Milliseconds to wait between bots joining,
+//!   replacing the `[join]` schedule from the config.
+//! - `--pathfinder-debug-particles` or `-P`: Whether the bots should run
+//!   /particle a ton of times to show where they're pathfinding to. You should
+//!   only have this on if the bots have operator permissions, otherwise it'll
+//!   just spam the server console unnecessarily.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+use azalea::Account;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    command_controler::{BotTask, TaskLimits},
+    kick::{KickPolicy, KickReason},
+    permissions::{Permissions, Role},
+};
+
+/// An example:
+///
+/// ```toml
+/// join_delay_ms = 100
+/// chat_delay_ms = 1000
+/// accounts = { prefix = "bot", count = 3 }
+///
+/// [server]
+/// address = "localhost"
+/// port = 25565
+///
+/// [bot]
+/// killaura = true
+/// view_distance = 16
+///
+/// [bots.bot0]
+/// killaura = false
+///
+/// [tasks.goto]
+/// timeout = 120
+/// stall = 60
+///
+/// [waypoints]
+/// spawn = [0, 64, 0]
+/// farm = [120, 70, -45]
+///
+/// [join]
+/// mode = "batch"
+/// size = 5
+/// delay_ms = 10000
+///
+/// [persistence]
+/// path = "tasks.json"
+/// interval_secs = 10
+///
+/// [api]
+/// address = "127.0.0.1:7878"
+/// token = "change me"
+///
+/// [metrics]
+/// address = "127.0.0.1:9898"
+///
+/// [reconnect]
+/// max_retries = 10
+/// give_up_on = ["you are not welcome here"]
+/// interrupted_task = "requeue"
+/// alert = ["banned", "combat_log"]
+///
+/// [reconnect.policies]
+/// throttled = "give_up"
+///
+/// [logging]
+/// level = "info,rustbot::killaura=debug"
+/// file = "logs/rustbot.log"
+/// rotation = "daily"
+///
+/// [permissions.owners]
+/// Astatin3 = "admin"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub accounts: Accounts,
+    /// Shorthand for a fixed `[join]` schedule, used if there's no `[join]`.
+    pub join_delay_ms: u64,
+    pub join: Option<JoinSchedule>,
+    /// How long a chat task waits between messages.
+    pub chat_delay_ms: u64,
+    pub permissions: Permissions,
+    /// Defaults for every bot.
+    pub bot: BotSettings,
+    /// Per-bot overrides of `bot`, by username.
+    pub bots: HashMap<String, BotOverrides>,
+    /// Limits for each task type, by task name, replacing its built-in ones.
+    pub tasks: HashMap<String, LimitsConfig>,
+    /// Named blocks for `!goto <name>`.
+    pub waypoints: HashMap<String, [i32; 3]>,
+    pub persistence: PersistenceConfig,
+    pub api: ApiConfig,
+    pub metrics: MetricsConfig,
+    pub reconnect: ReconnectConfig,
+    /// Only read at startup.
+    pub logging: LoggingConfig,
+    /// The command line this config was loaded with, kept to reload it.
+    #[serde(skip)]
+    args: Args,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            server: ServerConfig::default(),
+            accounts: Accounts::Generated {
+                prefix: "bot".to_string(),
+                count: 3,
+            },
+            join_delay_ms: 0,
+            join: None,
+            chat_delay_ms: 1000,
+            permissions: Permissions::default(),
+            bot: BotSettings::default(),
+            bots: HashMap::new(),
+            tasks: HashMap::new(),
+            waypoints: HashMap::new(),
+            persistence: PersistenceConfig::default(),
+            api: ApiConfig::default(),
+            metrics: MetricsConfig::default(),
+            reconnect: ReconnectConfig::default(),
+            logging: LoggingConfig::default(),
+            args: Args::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub address: String,
+    pub port: Option<u16>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: "localhost".to_string(),
+            port: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn join_address(&self) -> String {
+        match self.port {
+            Some(port) => format!("{}:{}", self.address, port),
+            None => self.address.clone(),
+        }
+    }
+}
+
+/// Either an explicit list of usernames and emails, or `count` offline
+/// accounts named `<prefix><i>`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Accounts {
+    List(Vec<String>),
+    Generated { prefix: String, count: usize },
+}
+
+impl Accounts {
+    pub fn usernames(&self) -> Vec<String> {
+        match self {
+            Accounts::List(accounts) => accounts.clone(),
+            Accounts::Generated { prefix, count } => {
+                (0..*count).map(|i| format!("{}{}", prefix, i)).collect()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BotSettings {
+    pub killaura: bool,
+    pub view_distance: u8,
+    pub pathfinder_debug_particles: bool,
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        Self {
+            killaura: false,
+            view_distance: 8,
+            pathfinder_debug_particles: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BotOverrides {
+    pub killaura: Option<bool>,
+    pub view_distance: Option<u8>,
+    pub pathfinder_debug_particles: Option<bool>,
+}
+
+/// Seconds and ticks, with 0 disabling the limit.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    pub timeout: Option<u64>,
+    pub stall: Option<u32>,
+}
+
+/// Where the task queue is saved so it survives a restart, and how often.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PersistenceConfig {
+    pub path: PathBuf,
+    /// 0 to never save.
+    pub interval_secs: u64,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("tasks.json"),
+            interval_secs: 10,
+        }
+    }
+}
+
+impl PersistenceConfig {
+    pub fn interval(&self) -> Option<Duration> {
+        (self.interval_secs > 0).then(|| Duration::from_secs(self.interval_secs))
+    }
+}
+
+/// The local HTTP API. It only starts if a token is set, since anyone who can
+/// reach it can command the swarm.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    pub address: String,
+    /// Expected as `Authorization: Bearer <token>` on every request.
+    pub token: String,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:7878".to_string(),
+            token: String::new(),
+        }
+    }
+}
+
+/// How far apart bots join, so servers that throttle connections don't kick
+/// them for joining too fast.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum JoinSchedule {
+    /// The same wait before every bot.
+    Fixed { delay_ms: u64 },
+    /// A wait picked between the two before every bot.
+    Random { min_ms: u64, max_ms: u64 },
+    /// `size` bots at once, waiting `delay_ms` between batches.
+    Batch { size: usize, delay_ms: u64 },
+}
+
+impl JoinSchedule {
+    /// How long to wait before the bot after `joined` others, so the first
+    /// one always joins right away.
+    pub fn delay(&self, joined: usize) -> Duration {
+        if joined == 0 {
+            return Duration::ZERO;
+        }
+        let delay_ms = match *self {
+            JoinSchedule::Fixed { delay_ms } => delay_ms,
+            JoinSchedule::Random { min_ms, max_ms } => {
+                rand::random_range(min_ms.min(max_ms)..=max_ms.max(min_ms))
+            }
+            JoinSchedule::Batch { size, delay_ms } => {
+                if joined % size.max(1) == 0 {
+                    delay_ms
+                } else {
+                    0
+                }
+            }
+        };
+        Duration::from_millis(delay_ms)
+    }
+}
+
+/// Where `/metrics` is served, without a token. Off if there's no address.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub address: Option<String>,
+}
+
+/// How bots that got disconnected rejoin.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ReconnectConfig {
+    /// Wait before the first attempt, doubled by `multiplier` for each one
+    /// after that up to `max_delay_ms`.
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub multiplier: f64,
+    /// Randomly shortens or lengthens each wait by up to this fraction, so
+    /// bots kicked together don't all rejoin together.
+    pub jitter: f64,
+    /// Attempts in a row before giving up on a bot. Retries forever if unset.
+    pub max_retries: Option<u32>,
+    /// Kick messages containing any of these, ignoring case, aren't retried
+    /// whatever their reason.
+    pub give_up_on: Vec<String>,
+    /// What to do for each kick reason, replacing its default policy.
+    pub policies: HashMap<KickReason, KickPolicy>,
+    /// How long to wait between attempts while the server restarts.
+    pub restart_delay_ms: u64,
+    /// Kick reasons the owners get whispered about by another bot.
+    pub alert: Vec<KickReason>,
+    pub interrupted_task: InterruptedTask,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay_ms: 1000,
+            max_delay_ms: 60_000,
+            multiplier: 2.0,
+            jitter: 0.25,
+            max_retries: None,
+            give_up_on: Vec::new(),
+            policies: HashMap::new(),
+            restart_delay_ms: 30_000,
+            alert: vec![
+                KickReason::Banned,
+                KickReason::NotWhitelisted,
+                KickReason::Outdated,
+                KickReason::CombatLog,
+            ],
+            interrupted_task: InterruptedTask::Resume,
+        }
+    }
+}
+
+/// What happens to the task a bot was running when it got disconnected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InterruptedTask {
+    /// The same bot carries on with it once it's back.
+    Resume,
+    /// Whichever bot is idle first carries on with it.
+    Requeue,
+    Drop,
+}
+
+impl ReconnectConfig {
+    pub fn policy(&self, kick: KickReason) -> KickPolicy {
+        self.policies
+            .get(&kick)
+            .copied()
+            .unwrap_or(kick.default_policy())
+    }
+
+    /// How long to wait before reconnect attempt `attempt`, counting from 1,
+    /// or `None` to give up.
+    pub fn delay(&self, attempt: u32, kick: KickReason, message: Option<&str>) -> Option<Duration> {
+        if let Some(message) = message {
+            let message = message.to_lowercase();
+            if self
+                .give_up_on
+                .iter()
+                .any(|pattern| message.contains(pattern.to_lowercase().as_str()))
+            {
+                return None;
+            }
+        }
+        match self.policy(kick) {
+            KickPolicy::GiveUp => return None,
+            KickPolicy::WaitForRestart => {
+                return Some(Duration::from_millis(self.restart_delay_ms));
+            }
+            KickPolicy::Retry => {}
+        }
+        if self
+            .max_retries
+            .is_some_and(|max_retries| attempt > max_retries)
+        {
+            return None;
+        }
+        let backoff = self.initial_delay_ms as f64 * self.multiplier.powi(attempt as i32 - 1);
+        let delay = backoff.min(self.max_delay_ms as f64);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 + rand::random_range(-jitter..=jitter);
+        Some(Duration::from_secs_f64(delay * factor / 1000.0))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Levels as `RUST_LOG` directives, e.g. `info,rustbot::killaura=debug`.
+    /// `RUST_LOG` itself overrides this.
+    pub level: String,
+    /// Log one JSON object per line instead of text.
+    pub json: bool,
+    /// Also log to this file, rotated into `<file>.<date>` files.
+    pub file: Option<PathBuf>,
+    pub rotation: LogRotation,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            json: false,
+            file: None,
+            rotation: LogRotation::Daily,
+        }
+    }
+}
+
+/// How often the log file is rotated.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl Config {
+    /// Reads the config file named on the command line, then applies the rest
+    /// of the arguments on top of it.
+    pub fn load() -> anyhow::Result<Self> {
+        Self::load_with(parse_args())
+    }
+
+    /// Reads the config file again with the same command line overrides.
+    pub fn reload(&self) -> anyhow::Result<Self> {
+        Self::load_with(self.args.clone())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.args.config
+    }
+
+    fn load_with(args: Args) -> anyhow::Result<Self> {
+        let mut config = Self::read(&args.config)?;
+
+        if let Some(accounts) = &args.accounts {
+            config.accounts = Accounts::List(accounts.clone());
+        }
+        if let Some(server) = &args.server {
+            config.server.address = server.clone();
+        }
+        if let Some(join_delay_ms) = args.join_delay_ms {
+            config.join_delay_ms = join_delay_ms;
+            config.join = None;
+        }
+        if args.pathfinder_debug_particles {
+            config.bot.pathfinder_debug_particles = true;
+        }
+        for owner in &args.owners {
+            config.permissions.owners.insert(owner.clone(), Role::Admin);
+        }
+        config.args = args;
+        Ok(config)
+    }
+
+    /// Logs settings that are probably mistakes. Separate from loading since
+    /// logging is set up from the loaded config.
+    pub fn check(&self) {
+        if !self.path().exists() {
+            warn!(
+                "{} not found, using the default config",
+                self.path().display()
+            );
+        }
+        if self.permissions.owners.is_empty() && self.permissions.default_role.is_none() {
+            warn!("No owners configured, no player can command the swarm");
+        }
+    }
+
+    /// The config file alone, or the defaults if there isn't one.
+    pub fn read(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(toml::from_str(fs::read_to_string(path)?.as_str())?)
+    }
+
+    pub async fn accounts(&self) -> anyhow::Result<Vec<Account>> {
+        let mut accounts = Vec::new();
+        for username_or_email in self.accounts.usernames() {
+            accounts.push(account(username_or_email.as_str()).await?);
+        }
+        Ok(accounts)
+    }
+
+    pub fn join_schedule(&self) -> JoinSchedule {
+        self.join.clone().unwrap_or(JoinSchedule::Fixed {
+            delay_ms: self.join_delay_ms,
+        })
+    }
+
+    pub fn chat_delay(&self) -> Duration {
+        Duration::from_millis(self.chat_delay_ms)
+    }
+
+    /// The `[bot]` defaults with the overrides for `username` applied.
+    pub fn bot_settings(&self, username: &str) -> BotSettings {
+        let mut settings = self.bot.clone();
+        if let Some(overrides) = self.bots.get(username) {
+            settings.killaura = overrides.killaura.unwrap_or(settings.killaura);
+            settings.view_distance = overrides.view_distance.unwrap_or(settings.view_distance);
+            settings.pathfinder_debug_particles = overrides
+                .pathfinder_debug_particles
+                .unwrap_or(settings.pathfinder_debug_particles);
+        }
+        settings
+    }
+
+    /// The task type's built-in limits with the `[tasks.<name>]` overrides.
+    pub fn task_limits(&self, task: &dyn BotTask) -> TaskLimits {
+        let mut limits = task.default_limits();
+        let configured = self
+            .tasks
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(task.get_name()));
+        if let Some((_, configured)) = configured {
+            if let Some(timeout) = configured.timeout {
+                limits.timeout = (timeout != 0).then(|| Duration::from_secs(timeout));
+            }
+            if let Some(stall) = configured.stall {
+                limits.stall_ticks = (stall != 0).then_some(stall);
+            }
+        }
+        limits
+    }
+}
+
+/// Offline accounts by username, Microsoft accounts by email.
+pub async fn account(username_or_email: &str) -> anyhow::Result<Account> {
+    if username_or_email.contains('@') {
+        Ok(Account::microsoft(username_or_email).await?)
+    } else {
+        Ok(Account::offline(username_or_email))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Args {
+    config: PathBuf,
+    owners: Vec<String>,
+    accounts: Option<Vec<String>>,
+    server: Option<String>,
+    join_delay_ms: Option<u64>,
+    pathfinder_debug_particles: bool,
+}
+
+fn parse_args() -> Args {
+    let mut config = PathBuf::from("config.toml");
+    let mut owners = Vec::new();
+    let mut accounts: Option<Vec<String>> = None;
+    let mut server = None;
+    let mut join_delay_ms = None;
+    let mut pathfinder_debug_particles = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" | "-C" => {
+                config = PathBuf::from(args.next().expect("Missing config path"));
+            }
+            "--owner" | "-O" => {
+                owners.push(args.next().expect("Missing owner username"));
+            }
+            "--account" | "-A" => {
+                for account in args.next().expect("Missing account").split(',') {
+                    accounts
+                        .get_or_insert_with(Vec::new)
+                        .push(account.to_string());
+                }
+            }
+            "--server" | "-S" => {
+                server = Some(args.next().expect("Missing server address"));
+            }
+            "--join-delay" | "-J" => {
+                let delay = args.next().expect("Missing join delay");
+                join_delay_ms = Some(delay.parse().expect("Join delay must be milliseconds"));
+            }
+            "--pathfinder-debug-particles" | "-P" => {
+                pathfinder_debug_particles = true;
+            }
+            _ => {
+                eprintln!("Unknown argument: {}", arg);
+                process::exit(1);
+            }
+        }
+    }
+
+    Args {
+        config,
+        owners,
+        accounts,
+        server,
+        join_delay_ms,
+        pathfinder_debug_particles,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn without_jitter() -> ReconnectConfig {
+        ReconnectConfig {
+            jitter: 0.0,
+            ..ReconnectConfig::default()
+        }
+    }
+
+    #[test]
+    fn backs_off_up_to_the_max_delay() {
+        let config = without_jitter();
+        let delay = |attempt| config.delay(attempt, KickReason::Other, None);
+        assert_eq!(delay(1), Some(Duration::from_secs(1)));
+        assert_eq!(delay(2), Some(Duration::from_secs(2)));
+        assert_eq!(delay(3), Some(Duration::from_secs(4)));
+        assert_eq!(delay(10), Some(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn jitters_within_bounds() {
+        let config = ReconnectConfig::default();
+        for _ in 0..100 {
+            let delay = config.delay(1, KickReason::Timeout, None).unwrap();
+            assert!(delay >= Duration::from_millis(750) && delay <= Duration::from_millis(1250));
+        }
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let config = ReconnectConfig {
+            max_retries: Some(3),
+            ..without_jitter()
+        };
+        assert!(config.delay(3, KickReason::Other, None).is_some());
+        assert_eq!(config.delay(4, KickReason::Other, None), None);
+        // waiting for a restart doesn't use up retries
+        assert_eq!(
+            config.delay(10, KickReason::ServerRestart, None),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn follows_the_policy_for_the_kick() {
+        let mut config = without_jitter();
+        assert_eq!(config.delay(1, KickReason::Banned, None), None);
+        assert_eq!(config.delay(1, KickReason::Outdated, None), None);
+        config
+            .policies
+            .insert(KickReason::Banned, KickPolicy::Retry);
+        assert_eq!(
+            config.delay(1, KickReason::Banned, None),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn gives_up_on_matching_messages() {
+        let config = ReconnectConfig {
+            give_up_on: vec!["Account Suspended".to_string()],
+            ..without_jitter()
+        };
+        assert_eq!(
+            config.delay(
+                1,
+                KickReason::Other,
+                Some("Your ACCOUNT SUSPENDED until May")
+            ),
+            None
+        );
+        assert!(config.delay(1, KickReason::Other, Some("Kicked")).is_some());
+    }
+}
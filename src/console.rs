@@ -0,0 +1,130 @@
+//! Commands typed into the terminal the swarm runs in. Takes the same commands
+//! as chat, without the `!`, and prints the replies instead of chatting them.
+//! Start a line with `@bot1` to give the task to one bot.
+
+use std::io::{self, IsTerminal};
+
+use azalea::swarm::Swarm;
+use rustyline::{
+    Context, Editor, Helper, Highlighter, Hinter, Validator, completion::Completer,
+    error::ReadlineError, history::DefaultHistory,
+};
+use tracing::error;
+
+use crate::{
+    SwarmState,
+    commands::{CommandSource, Origin},
+    persistence::{self, SwarmSnapshot},
+};
+
+static HISTORY_FILE: &str = ".console_history";
+
+/// Reads commands from stdin until it's closed or Ctrl-C is pressed, which
+/// saves the tasks and exits. Does nothing if stdin isn't a terminal.
+pub fn start(swarm: Swarm, state: SwarmState) {
+    if !io::stdin().is_terminal() {
+        return;
+    }
+    tokio::task::spawn_blocking(move || {
+        let mut editor = match Editor::<ConsoleHelper, DefaultHistory>::new() {
+            Ok(editor) => editor,
+            Err(err) => {
+                error!("Failed to start the console: {}", err);
+                return;
+            }
+        };
+        editor.set_helper(Some(ConsoleHelper {
+            swarm: swarm.clone(),
+            state: state.clone(),
+        }));
+        let _ = editor.load_history(HISTORY_FILE);
+
+        loop {
+            match editor.readline("> ") {
+                Ok(line) => {
+                    let line = line.trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let _ = editor.add_history_entry(line);
+                    CommandSource::new(swarm.clone(), state.clone(), Origin::Console)
+                        .run(line.trim_start_matches('!'));
+                }
+                Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+                Err(err) => {
+                    error!("Console error: {}", err);
+                    break;
+                }
+            }
+        }
+
+        let _ = editor.save_history(HISTORY_FILE);
+        let path = state.config.read().persistence.path.clone();
+        if let Err(err) = persistence::save(&path, &SwarmSnapshot::capture(&swarm, &state)) {
+            error!("Failed to save tasks to {}: {}", path.display(), err);
+        }
+        state.shutdown.notify_one();
+    });
+}
+
+#[derive(Helper, Highlighter, Hinter, Validator)]
+struct ConsoleHelper {
+    swarm: Swarm,
+    state: SwarmState,
+}
+
+impl ConsoleHelper {
+    /// Literal names that can follow `words`, like the commands themselves or
+    /// `all` after `cancel`.
+    fn literals_after(&self, words: &[&str], partial: &str) -> Vec<String> {
+        let mut node = self.state.commands.root.clone();
+        for word in words {
+            let next = node.read().literals.get(*word).cloned();
+            match next {
+                Some(next) => node = next,
+                None => return Vec::new(),
+            }
+        }
+        let mut literals = node
+            .read()
+            .literals
+            .keys()
+            .filter(|name| name.starts_with(partial))
+            .cloned()
+            .collect::<Vec<String>>();
+        literals.sort();
+        literals
+    }
+}
+
+impl Completer for ConsoleHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let partial = line.rsplit(char::is_whitespace).next().unwrap_or_default();
+        let start = pos - partial.len();
+        let words = line[..start]
+            .split_whitespace()
+            .filter(|word| !word.starts_with('@') && !word.contains('='))
+            .collect::<Vec<&str>>();
+
+        if let Some(bot) = partial.strip_prefix('@') {
+            let mut bots = self
+                .swarm
+                .clone()
+                .into_iter()
+                .map(|bot| format!("@{}", bot.username()))
+                .filter(|name| name[1..].starts_with(bot))
+                .collect::<Vec<String>>();
+            bots.sort();
+            return Ok((start, bots));
+        }
+        Ok((start, self.literals_after(&words, partial)))
+    }
+}
@@ -0,0 +1,120 @@
+//! What the swarm is doing, published for anything outside the game that
+//! wants to follow along, like the HTTP API's event streams.
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::{command_controler::TaskOutcome, kick::KickReason};
+
+/// How many events a slow subscriber can fall behind before it misses some.
+static CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SwarmEventKind {
+    Joined {
+        bot: String,
+    },
+    Disconnected {
+        bot: String,
+        kick: KickReason,
+        reason: Option<String>,
+    },
+    Died {
+        bot: String,
+        message: Option<String>,
+    },
+    /// A killaura hit.
+    Attacked {
+        bot: String,
+        entity_id: i32,
+        distance: f64,
+    },
+    TaskStarted {
+        bot: String,
+        task: String,
+    },
+    TaskEnded {
+        bot: String,
+        task: String,
+        outcome: TaskOutcome,
+    },
+    Chat {
+        sender: Option<String>,
+        content: String,
+    },
+}
+
+impl SwarmEventKind {
+    /// The `type` the event is serialized with.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SwarmEventKind::Joined { .. } => "joined",
+            SwarmEventKind::Disconnected { .. } => "disconnected",
+            SwarmEventKind::Died { .. } => "died",
+            SwarmEventKind::Attacked { .. } => "attacked",
+            SwarmEventKind::TaskStarted { .. } => "task_started",
+            SwarmEventKind::TaskEnded { .. } => "task_ended",
+            SwarmEventKind::Chat { .. } => "chat",
+        }
+    }
+
+    /// The bot the event is about. For chat that's the sender, which is only
+    /// one of the bots if they said it themselves.
+    pub fn bot(&self) -> Option<&str> {
+        match self {
+            SwarmEventKind::Joined { bot }
+            | SwarmEventKind::Disconnected { bot, .. }
+            | SwarmEventKind::Died { bot, .. }
+            | SwarmEventKind::Attacked { bot, .. }
+            | SwarmEventKind::TaskStarted { bot, .. }
+            | SwarmEventKind::TaskEnded { bot, .. } => Some(bot.as_str()),
+            SwarmEventKind::Chat { sender, .. } => sender.as_deref(),
+        }
+    }
+}
+
+/// Which events a subscriber wants, from a query string like
+/// `?bots=bot0,bot1&types=task_ended,died`. A missing list lets everything
+/// through.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct EventFilter {
+    pub bots: Option<String>,
+    pub types: Option<String>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &SwarmEventKind) -> bool {
+        let listed = |list: &Option<String>, value: Option<&str>| {
+            list.as_ref().is_none_or(|list| {
+                value.is_some_and(|value| list.split(',').any(|item| item.trim() == value))
+            })
+        };
+        listed(&self.bots, event.bot()) && listed(&self.types, Some(event.kind()))
+    }
+}
+
+#[derive(Clone)]
+pub struct Events {
+    sender: broadcast::Sender<SwarmEventKind>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl Events {
+    /// Sends the event to every subscriber. Nothing happens if there are none.
+    pub fn publish(&self, event: SwarmEventKind) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SwarmEventKind> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,75 @@
+//! Adds the bots to the swarm on the `[join]` schedule instead of all at once.
+//! The swarm starts with no accounts and the bots wait here until it's their
+//! turn, see [`JoinSchedule`](crate::config::JoinSchedule).
+
+use std::{collections::VecDeque, sync::Arc};
+
+use azalea::{Account, JoinOpts, swarm::Swarm};
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::{BotState, SwarmState, reconnect};
+
+/// The accounts waiting to join, in order.
+#[derive(Clone, Default)]
+pub struct Joins {
+    waiting: Arc<Mutex<VecDeque<Account>>>,
+    notify: Arc<Notify>,
+}
+
+impl Joins {
+    pub fn push(&self, account: Account) {
+        self.waiting.lock().push_back(account);
+        self.notify.notify_one();
+    }
+
+    /// Takes a bot out of the line, like one removed from the config before
+    /// its turn.
+    pub fn remove(&self, username: &str) {
+        self.waiting
+            .lock()
+            .retain(|account| account.username != username);
+    }
+
+    pub fn usernames(&self) -> Vec<String> {
+        self.waiting
+            .lock()
+            .iter()
+            .map(|account| account.username.clone())
+            .collect()
+    }
+}
+
+/// Joins the waiting bots as long as the swarm runs, so accounts added by a
+/// reload are spaced out too.
+pub fn start(swarm: Swarm, state: SwarmState) {
+    tokio::spawn(async move {
+        let mut joined = 0;
+        loop {
+            if state.joins.waiting.lock().is_empty() {
+                // the next bot starts a new schedule, there's nobody to space it from
+                joined = 0;
+                state.joins.notify.notified().await;
+                continue;
+            }
+            let delay = state.config.read().join_schedule().delay(joined);
+            tokio::time::sleep(delay).await;
+
+            let Some(account) = state.joins.waiting.lock().pop_front() else {
+                continue;
+            };
+            info!(bot = %account.username, "Joining");
+            if let Err(err) = swarm.add(&account, BotState::default()).await {
+                // retried like a disconnect, without holding up the bots after it
+                warn!(bot = %account.username, "Failed to join: {}", err);
+                state.reconnects.failed(&account.username, err.to_string());
+                let (swarm, state) = (swarm.clone(), state.clone());
+                tokio::spawn(async move {
+                    reconnect::rejoin(&swarm, &state, &account, &JoinOpts::default()).await;
+                });
+            }
+            joined += 1;
+        }
+    });
+}
@@ -0,0 +1,143 @@
+//! Sorts kick messages into the few reasons the reconnect policy cares about.
+//! Servers word these however they like, so this goes by keywords shared by
+//! vanilla and the common server software.
+
+use std::fmt::{self, Display};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KickReason {
+    ServerRestart,
+    Throttled,
+    Banned,
+    NotWhitelisted,
+    Outdated,
+    Timeout,
+    /// Killed for logging out in combat, by a combat-log plugin.
+    CombatLog,
+    Other,
+}
+
+/// Keywords for each reason, checked in order against the lowercased message.
+static KEYWORDS: [(KickReason, &[&str]); 7] = [
+    (KickReason::Banned, &["banned"]),
+    (KickReason::NotWhitelisted, &["whitelist", "white-list"]),
+    (
+        KickReason::Outdated,
+        &["outdated", "incompatible", "unsupported version"],
+    ),
+    (
+        KickReason::Throttled,
+        &["throttle", "wait before reconnecting", "logged in too fast"],
+    ),
+    (
+        KickReason::ServerRestart,
+        &[
+            "restart",
+            "shutting down",
+            "server closed",
+            "server stopped",
+        ],
+    ),
+    (KickReason::CombatLog, &["combat"]),
+    (
+        KickReason::Timeout,
+        &["timed out", "timeout", "keepalive", "keep alive"],
+    ),
+];
+
+/// What to do about a bot that got kicked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KickPolicy {
+    /// Back off and try again, up to the retry limit.
+    Retry,
+    /// Wait the fixed restart delay each time, without using up retries.
+    WaitForRestart,
+    GiveUp,
+}
+
+impl KickReason {
+    pub fn classify(message: Option<&str>) -> Self {
+        let Some(message) = message else {
+            return KickReason::Other;
+        };
+        let message = message.to_lowercase();
+        KEYWORDS
+            .iter()
+            .find(|(_, keywords)| keywords.iter().any(|keyword| message.contains(keyword)))
+            .map_or(KickReason::Other, |(reason, _)| *reason)
+    }
+
+    /// The policy unless the config says otherwise.
+    pub fn default_policy(self) -> KickPolicy {
+        match self {
+            KickReason::ServerRestart => KickPolicy::WaitForRestart,
+            KickReason::Banned | KickReason::NotWhitelisted | KickReason::Outdated => {
+                KickPolicy::GiveUp
+            }
+            KickReason::Throttled
+            | KickReason::Timeout
+            | KickReason::CombatLog
+            | KickReason::Other => KickPolicy::Retry,
+        }
+    }
+}
+
+impl Display for KickReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            KickReason::ServerRestart => "server restart",
+            KickReason::Throttled => "throttled",
+            KickReason::Banned => "banned",
+            KickReason::NotWhitelisted => "not whitelisted",
+            KickReason::Outdated => "outdated client",
+            KickReason::Timeout => "timed out",
+            KickReason::CombatLog => "combat log",
+            KickReason::Other => "other",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_common_kick_messages() {
+        let cases = [
+            ("You are banned from this server!", KickReason::Banned),
+            (
+                "You are not white-listed on this server!",
+                KickReason::NotWhitelisted,
+            ),
+            ("Outdated client! Please use 1.21.5", KickReason::Outdated),
+            (
+                "Connection throttled! Please wait before reconnecting.",
+                KickReason::Throttled,
+            ),
+            ("Server closed", KickReason::ServerRestart),
+            ("The server is restarting", KickReason::ServerRestart),
+            ("You logged out in combat", KickReason::CombatLog),
+            ("Timed out", KickReason::Timeout),
+            ("disconnect.timeout", KickReason::Timeout),
+            ("Kicked by an operator", KickReason::Other),
+        ];
+        for (message, reason) in cases {
+            assert_eq!(KickReason::classify(Some(message)), reason, "{}", message);
+        }
+        assert_eq!(KickReason::classify(None), KickReason::Other);
+    }
+
+    #[test]
+    fn checks_keywords_in_order() {
+        // a ban that mentions the restart is still a ban
+        assert_eq!(
+            KickReason::classify(Some("Banned until the next restart")),
+            KickReason::Banned
+        );
+    }
+}
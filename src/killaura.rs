@@ -5,12 +5,11 @@ use azalea::{
     world::{InstanceName, MinecraftEntityId},
 };
 
-use crate::State;
+use tracing::debug;
 
-pub fn tick(bot: Client, state: State) -> anyhow::Result<()> {
-    if !state.killaura {
-        return Ok(());
-    }
+use crate::{SwarmState, events::SwarmEventKind};
+
+pub fn tick(bot: Client) -> anyhow::Result<()> {
     if bot.has_attack_cooldown() {
         return Ok(());
     }
@@ -39,9 +38,15 @@ pub fn tick(bot: Client, state: State) -> anyhow::Result<()> {
         }
     }
     if let Some(nearest_entity) = nearest_entity {
-        println!("attacking {:?}", nearest_entity);
-        println!("distance {:?}", nearest_distance);
+        debug!(entity = ?nearest_entity, distance = nearest_distance, "Attacking");
         bot.attack(nearest_entity);
+        let swarm_state = bot.resource::<SwarmState>();
+        swarm_state.metrics.attacked();
+        swarm_state.events.publish(SwarmEventKind::Attacked {
+            bot: bot.username(),
+            entity_id: nearest_entity.0,
+            distance: nearest_distance,
+        });
     }
 
     Ok(())
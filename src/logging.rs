@@ -0,0 +1,62 @@
+//! Sets up `tracing` from the `[logging]` table of the config. Every bot's
+//! events are logged inside a `bot` span and every task inside a `task` span,
+//! so each line says which bot and task it came from.
+
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{EnvFilter, Layer, Registry, fmt, prelude::*};
+
+use crate::config::{LogRotation, LoggingConfig};
+
+/// Installs the global subscriber. The returned guard flushes the log file
+/// when dropped, so it has to live as long as the program.
+pub fn init(config: &LoggingConfig) -> anyhow::Result<Option<WorkerGuard>> {
+    // RUST_LOG takes precedence so a run can be debugged without editing the config
+    let filter =
+        EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new(&config.level))?;
+
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = Vec::new();
+    layers.push(if config.json {
+        fmt::layer().json().boxed()
+    } else {
+        fmt::layer().boxed()
+    });
+
+    let mut guard = None;
+    if let Some(path) = &config.file {
+        let directory = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(".".as_ref());
+        let prefix = path.file_name().unwrap_or("rustbot.log".as_ref());
+        let appender = RollingFileAppender::new(config.rotation.into(), directory, prefix);
+        let (writer, file_guard) = tracing_appender::non_blocking(appender);
+        guard = Some(file_guard);
+
+        let layer = fmt::layer().with_ansi(false).with_writer(writer);
+        layers.push(if config.json {
+            layer.json().boxed()
+        } else {
+            layer.boxed()
+        });
+    }
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(filter)
+        .try_init()?;
+    Ok(guard)
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
@@ -1,32 +1,91 @@
-use std::{alloc::System, sync::Arc};
+use std::{sync::Arc, time::Instant};
 
+pub mod api;
 pub mod bot_task;
+pub mod chat;
 pub mod command_controler;
+pub mod commands;
+pub mod config;
+pub mod console;
+pub mod events;
+pub mod join;
+pub mod kick;
+pub mod killaura;
+pub mod logging;
+pub mod metrics;
+pub mod permissions;
+pub mod persistence;
+pub mod reconnect;
+pub mod reload;
+pub mod status;
+pub mod task_plugin;
 
 use azalea::{
+    ClientInformation, DefaultBotPlugins, DefaultPlugins,
+    app::PluginGroup,
+    brigadier::command_dispatcher::CommandDispatcher,
+    pathfinder::debug::PathfinderDebugParticles,
     prelude::*,
-    swarm::{Swarm, SwarmBuilder, SwarmEvent},
+    swarm::{DefaultSwarmPlugins, Swarm, SwarmBuilder, SwarmEvent},
 };
-use command_controler::BotTask;
-use parking_lot::Mutex;
-
-use crate::bot_task::*;
-
-pub static BOT_COUNT: usize = 3;
-pub static BOT_PREFIX: &'static str = "bot";
+use bevy_log::LogPlugin;
+use command_controler::{
+    QueuedTask, RunningTask, TaskEvent, TaskOutcome, TaskTarget, insert_task, take_task,
+};
+use commands::{CommandSource, Origin, register_commands};
+use config::Config;
+use events::{Events, SwarmEventKind};
+use join::Joins;
+use kick::KickReason;
+use metrics::Metrics;
+use parking_lot::{Mutex, RwLock};
+use reconnect::Reconnects;
+use task_plugin::TaskPlugin;
+use tokio::sync::Notify;
+use tracing::{Instrument, info, info_span, warn};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    SwarmBuilder::new()
-        .add_accounts(
-            (0..BOT_COUNT)
-                .map(|i| Account::offline(format!("{}{}", BOT_PREFIX, i).as_str()))
-                .collect(),
-        )
+    let config = Config::load()?;
+    let _log_guard = logging::init(&config.logging)?;
+    config.check();
+    let tasks = persistence::load(&config.persistence.path);
+
+    let mut commands = CommandDispatcher::new();
+    register_commands(&mut commands);
+
+    // The bots are added from SwarmEvent::Init on the join schedule
+    let joins = Joins::default();
+    for account in config.accounts().await? {
+        joins.push(account);
+    }
+
+    let shutdown = Arc::new(Notify::new());
+    // Logging is set up above, so bevy's own subscriber has to stay out of the way
+    let swarm = SwarmBuilder::new_without_plugins()
+        .add_plugins(DefaultPlugins.build().disable::<LogPlugin>())
+        .add_plugins(DefaultBotPlugins)
+        .add_plugins(DefaultSwarmPlugins)
+        .add_plugins(TaskPlugin)
         .set_handler(handle)
         .set_swarm_handler(handle_swarm)
-        .start("localhost")
-        .await?
+        .set_swarm_state(SwarmState {
+            tasks: Arc::new(Mutex::new(tasks)),
+            commands: Arc::new(commands),
+            config: Arc::new(RwLock::new(config.clone())),
+            joins,
+            shutdown: shutdown.clone(),
+            ..Default::default()
+        })
+        .start(config.server.join_address());
+
+    // Exiting from here rather than wherever the shutdown was asked for drops
+    // the log guard, which flushes the lines still buffered
+    tokio::select! {
+        result = swarm => result?,
+        _ = shutdown.notified() => {}
+    }
+    Ok(())
 
     // ClientBuilder::new()
     //     .set_handler(handle)
@@ -38,40 +97,141 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 async fn handle(bot: Client, event: Event, state: BotState) -> anyhow::Result<()> {
-    if state.task.lock().is_some() {
-        // Process commands
-        if state.task.lock().as_ref().unwrap().end() {
-            bot.chat(
-                format!(
-                    "Finished {}",
-                    print_type_of(&state.task.lock().as_ref().unwrap())
-                )
-                .as_str(),
-            );
-            *state.task.lock() = None;
-        } else {
-            state.task.lock().as_mut().unwrap().on_event(&bot, &event);
+    let span = info_span!("bot", bot = %bot.username());
+    handle_event(bot, event, state).instrument(span).await
+}
+
+async fn handle_event(bot: Client, event: Event, state: BotState) -> anyhow::Result<()> {
+    // most events are packets nothing here cares about, so the swarm state
+    // is only looked up by the arms that need it
+    match &event {
+        Event::Login => {
+            info!("Joined");
+            let swarm_state = bot.resource::<SwarmState>();
+            swarm_state.reconnects.joined(bot.username().as_str());
+            swarm_state.events.publish(SwarmEventKind::Joined {
+                bot: bot.username(),
+            });
         }
-    } else {
-        let swarm_state = bot.resource::<SwarmState>();
-        if !swarm_state.tasks.lock().is_empty() {
-            *state.task.lock() = Some(swarm_state.tasks.lock().remove(0));
-            bot.chat(
-                format!(
-                    "Starting {}",
-                    print_type_of(&state.task.lock().as_ref().unwrap())
-                )
-                .as_str(),
-            );
+        Event::Disconnect(reason) => {
+            let reason = reason.as_ref().map(|reason| reason.to_string());
+            let kick = KickReason::classify(reason.as_deref());
+            warn!(%kick, ?reason, "Disconnected");
+            let swarm_state = bot.resource::<SwarmState>();
+            swarm_state.events.publish(SwarmEventKind::Disconnected {
+                bot: bot.username(),
+                kick,
+                reason: reason.clone(),
+            });
+            reconnect::interrupted(&bot, &state, &swarm_state, kick, reason);
+        }
+        Event::Death(packet) => {
+            warn!("Died");
+            bot.resource::<SwarmState>()
+                .events
+                .publish(SwarmEventKind::Died {
+                    bot: bot.username(),
+                    message: packet.as_ref().map(|packet| packet.message.to_string()),
+                });
+        }
+        Event::Init => {
+            let settings = bot
+                .resource::<SwarmState>()
+                .config
+                .read()
+                .bot_settings(bot.username().as_str());
+            bot.set_client_information(ClientInformation {
+                view_distance: settings.view_distance,
+                ..Default::default()
+            })
+            .await;
+            if settings.pathfinder_debug_particles {
+                bot.ecs
+                    .lock()
+                    .entity_mut(bot.entity)
+                    .insert(PathfinderDebugParticles);
+            }
+        }
+        Event::Tick => {
+            let started_at = Instant::now();
+            let swarm_state = bot.resource::<SwarmState>();
+            let killaura = swarm_state
+                .config
+                .read()
+                .bot_settings(bot.username().as_str())
+                .killaura;
+            if killaura {
+                killaura::tick(bot.clone())?;
+            }
+            run_task(&bot, &state, &swarm_state);
+            swarm_state.metrics.tick_took(started_at.elapsed());
+        }
+        _ => {}
+    }
+
+    // the task only hears about events it might care about, so most packets
+    // don't touch it at all
+    if let Some(task_event) = TaskEvent::from_event(&event) {
+        if let Some(current) = state.task.lock().as_mut() {
+            current.deliver(&bot, &task_event);
         }
     }
 
     Ok(())
 }
 
+/// Runs a tick of the bot's task, or hands it the next queued one once it's
+/// idle.
+fn run_task(bot: &Client, state: &BotState, swarm_state: &SwarmState) {
+    let mut task = state.task.lock();
+    match task.as_mut() {
+        Some(current) => {
+            if let Some(outcome) = current.tick(bot) {
+                report_outcome(bot, current.get_name(), &outcome);
+                *task = None;
+            }
+        }
+        None => {
+            let next = take_task(&mut swarm_state.tasks.lock(), bot.username().as_str());
+            if let Some(next) = next {
+                metrics::chat(bot, format!("Starting {}", next.task.get_name()).as_str());
+                swarm_state.metrics.task_started(next.task.get_name());
+                swarm_state.events.publish(SwarmEventKind::TaskStarted {
+                    bot: bot.username(),
+                    task: next.task.get_name().to_string(),
+                });
+                *task = Some(RunningTask::start(next.task, next.limits, bot));
+            }
+        }
+    }
+}
+
+/// Tells the swarm how a task ended, so failures aren't mistaken for successes.
+fn report_outcome(bot: &Client, name: &str, outcome: &TaskOutcome) {
+    let message = match outcome {
+        TaskOutcome::Success => format!("Finished {}", name),
+        TaskOutcome::Failed(reason) => format!("{} failed: {}", name, reason),
+        TaskOutcome::Cancelled => format!("{} cancelled", name),
+    };
+    match outcome {
+        TaskOutcome::Failed(_) => warn!("{}", message),
+        _ => info!("{}", message),
+    }
+    metrics::chat(bot, message.as_str());
+    let swarm_state = bot.resource::<SwarmState>();
+    swarm_state.metrics.task_ended(name, outcome);
+    swarm_state.events.publish(SwarmEventKind::TaskEnded {
+        bot: bot.username(),
+        task: name.to_string(),
+        outcome: outcome.clone(),
+    });
+}
+
 #[derive(Clone, Component)]
 pub struct BotState {
-    pub task: Arc<Mutex<Option<Box<dyn BotTask>>>>,
+    pub task: Arc<Mutex<Option<RunningTask>>>,
+    /// Every (re)connect gets a fresh state, so this is when the bot last joined.
+    pub joined_at: Instant,
     // pub messages_received: Arc<Mutex<usize>>,
 }
 
@@ -79,98 +239,171 @@ impl Default for BotState {
     fn default() -> Self {
         Self {
             task: Arc::new(Mutex::new(None)),
+            joined_at: Instant::now(),
         }
     }
 }
 
 impl BotState {
-    fn get_task(&self) -> String {
-        match self.task.lock().as_ref() {
-            Some(task) => task.get_name().to_string(),
-            None => "No task".to_string(),
-        }
+    /// Aborts the running task through its cancel path, so it can stop
+    /// pathfinding or anything else it started. Returns false if the bot was
+    /// idle.
+    pub fn cancel_task(&self, bot: &Client) -> bool {
+        let Some(mut task) = self.task.lock().take() else {
+            return false;
+        };
+        task.cancel(bot);
+        report_outcome(bot, task.get_name(), &TaskOutcome::Cancelled);
+        true
     }
 }
 
 #[derive(Resource, Default, Clone)]
-struct SwarmState {
-    pub tasks: Arc<Mutex<Vec<Box<dyn BotTask>>>>,
+pub struct SwarmState {
+    pub tasks: Arc<Mutex<Vec<QueuedTask>>>,
+    pub commands: Arc<CommandDispatcher<Mutex<CommandSource>>>,
+    pub config: Arc<RwLock<Config>>,
+    pub events: Events,
+    pub metrics: Arc<Metrics>,
+    pub reconnects: Reconnects,
+    /// Bots that haven't had their turn to join yet.
+    pub joins: Joins,
+    /// Notified to stop the swarm and exit, like when the console is closed.
+    pub shutdown: Arc<Notify>,
+}
+
+impl SwarmState {
+    /// Queues a task by priority. A task for every bot is split into one copy
+    /// per connected bot so each of them runs it exactly once. Fails if no bot
+    /// could ever pick it up.
+    pub fn enqueue(&self, swarm: &Swarm, task: QueuedTask) -> Result<(), String> {
+        if task.target == TaskTarget::All {
+            let usernames = swarm
+                .clone()
+                .into_iter()
+                .map(|bot| bot.username())
+                .collect::<Vec<String>>();
+            if usernames.is_empty() {
+                return Err("There are no bots to run it".to_string());
+            }
+            let mut tasks = self.tasks.lock();
+            for username in usernames {
+                insert_task(&mut tasks, task.for_bot(username.as_str()));
+            }
+            return Ok(());
+        }
+        if let TaskTarget::Bot(username) = &task.target {
+            if !self.knows_bot(swarm, username) {
+                return Err(format!("There's no bot called {}", username));
+            }
+        }
+        insert_task(&mut self.tasks.lock(), task);
+        Ok(())
+    }
+
+    /// Whether the bot called `username` is in the swarm or will be, once
+    /// it's had its turn to join or reconnected.
+    fn knows_bot(&self, swarm: &Swarm, username: &str) -> bool {
+        swarm
+            .clone()
+            .into_iter()
+            .any(|bot| bot.username() == username)
+            || self
+                .joins
+                .usernames()
+                .iter()
+                .any(|waiting| waiting == username)
+            || self
+                .reconnects
+                .disconnected_bots()
+                .iter()
+                .any(|bot| bot.username == username && !bot.gave_up)
+    }
+
+    /// Drops every task that no bot has picked up yet, returning how many.
+    pub fn clear_queue(&self) -> usize {
+        let mut tasks = self.tasks.lock();
+        let count = tasks.len();
+        tasks.clear();
+        count
+    }
+
+    /// Removes the unstarted task at `index`, as listed by `!status`.
+    pub fn remove_queued(&self, index: usize) -> Option<QueuedTask> {
+        let mut tasks = self.tasks.lock();
+        (index < tasks.len()).then(|| tasks.remove(index))
+    }
+}
+
+/// Why [`cancel_bot`] had nothing to cancel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotCancelled {
+    NoSuchBot,
+    Idle,
+}
+
+/// Cancels the running task on the bot called `username`.
+pub fn cancel_bot(swarm: &Swarm, username: &str) -> Result<(), NotCancelled> {
+    let bot = swarm
+        .clone()
+        .into_iter()
+        .find(|bot| bot.username() == username)
+        .ok_or(NotCancelled::NoSuchBot)?;
+    let cancelled = bot
+        .get_component::<BotState>()
+        .is_some_and(|state| state.cancel_task(&bot));
+    if cancelled {
+        Ok(())
+    } else {
+        Err(NotCancelled::Idle)
+    }
+}
+
+/// Cancels the running task on every bot, returning how many were stopped.
+pub fn cancel_all(swarm: &Swarm) -> usize {
+    swarm
+        .clone()
+        .into_iter()
+        .filter(|bot| {
+            bot.get_component::<BotState>()
+                .is_some_and(|state| state.cancel_task(bot))
+        })
+        .count()
 }
 
 async fn handle_swarm(swarm: Swarm, event: SwarmEvent, state: SwarmState) -> anyhow::Result<()> {
     match &event {
         SwarmEvent::Init => {
-            println!("Swarm initialized");
+            info!("Swarm initialized");
+            join::start(swarm.clone(), state.clone());
+            reload::watch(swarm.clone(), state.clone());
+            persistence::autosave(swarm.clone(), state.clone());
+            api::serve(swarm.clone(), state.clone());
+            metrics::serve(swarm.clone(), state.clone());
+            console::start(swarm.clone(), state.clone());
         }
         SwarmEvent::Login => {
-            println!("All bots have logged in");
+            info!("All bots have logged in");
         }
         SwarmEvent::Disconnect(account, join_opts) => {
-            println!("Bot {} disconnected", account.username);
-            swarm
-                .add_with_opts(account, BotState::default(), join_opts)
-                .await?;
+            info!(bot = %account.username, "Disconnected from the swarm");
+            reconnect::reconnect(&swarm, &state, account, join_opts).await;
         }
         SwarmEvent::Chat(msg) => {
-            let command = msg.content();
-            println!("Chat message: {}", command);
-
-            println!("{}", command);
-            if let Some(command) = {
-                let args = command
-                    .split_whitespace()
-                    .map(|s| s.to_string())
-                    .collect::<Vec<String>>();
-
-                match args[0].as_str() {
-                    "!chat" => Some(Box::new(Chat::init(args[1..].to_vec())) as Box<dyn BotTask>),
-                    "!goto" => {
-                        if let Some(task) = GotoBlock::parse(args[1..].to_vec()) {
-                            Some(Box::new(task) as Box<dyn BotTask>)
-                        } else {
-                            None
-                        }
-                    }
-                    "!status" => {
-                        for bot in swarm {
-                            let botstate = &bot.get_component::<BotState>().unwrap();
-                            println!("{} - {}", bot.username(), botstate.get_task());
-                        }
-                        println!("Unstarted: {:?}", state.tasks.lock());
-
-                        None
-                    }
-                    _ => None,
-                }
-            } {
-                state.tasks.lock().push(command);
-            }
+            let content = msg.content();
+            info!(sender = ?msg.sender(), "{}", content);
+            state.events.publish(SwarmEventKind::Chat {
+                sender: msg.sender(),
+                content: content.clone(),
+            });
 
-            // match state.commands.execute(
-            //     command,
-            //     Mutex::new(CommandSource {
-            //         bot: bot.clone(),
-            //         chat: chat.clone(),
-            //         state: state.clone(),
-            //     }),
-            // ) {
-            //     Ok(_) => {}
-            //     Err(err) => {
-            //         eprintln!("{err:?}");
-            //         let command_source = CommandSource {
-            //             bot,
-            //             chat: chat.clone(),
-            //             state: state.clone(),
-            //         };
-            //         command_source.reply(&format!("{err:?}"));
-            //     }
-            // }
+            let Some(command) = content.strip_prefix('!') else {
+                return Ok(());
+            };
+            CommandSource::new(swarm.clone(), state.clone(), Origin::Chat(msg.clone()))
+                .run(command);
         }
         _ => {}
     }
     Ok(())
 }
-
-fn print_type_of<T>(_: &T) -> String {
-    std::any::type_name::<T>().to_string()
-}
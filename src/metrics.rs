@@ -0,0 +1,295 @@
+//! Counters for how the swarm is doing over long runs, served in the
+//! Prometheus text format from `GET /metrics` on the `[metrics]` address.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use axum::{Router, extract::State, routing::get};
+use azalea::{prelude::*, swarm::Swarm};
+use parking_lot::Mutex;
+use tracing::{error, info};
+
+use crate::{BotState, SwarmState, command_controler::TaskOutcome};
+
+/// Upper bounds of the histogram buckets, in seconds.
+static PATHFINDING_BUCKETS: [f64; 7] = [1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+static TICK_BUCKETS: [f64; 7] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25];
+
+#[derive(Default)]
+pub struct Metrics {
+    /// By username.
+    reconnects: Mutex<HashMap<String, u64>>,
+    /// By task name.
+    tasks_started: Mutex<HashMap<String, u64>>,
+    /// By task name and outcome.
+    tasks_ended: Mutex<HashMap<(String, &'static str), u64>>,
+    attacks: AtomicU64,
+    chat_messages: AtomicU64,
+    pathfinding: Histogram<7>,
+    ticks: Histogram<7>,
+}
+
+impl Metrics {
+    pub fn reconnected(&self, username: &str) {
+        *self
+            .reconnects
+            .lock()
+            .entry(username.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn task_started(&self, name: &str) {
+        *self
+            .tasks_started
+            .lock()
+            .entry(name.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn task_ended(&self, name: &str, outcome: &TaskOutcome) {
+        let outcome = match outcome {
+            TaskOutcome::Success => "success",
+            TaskOutcome::Failed(_) => "failed",
+            TaskOutcome::Cancelled => "cancelled",
+        };
+        *self
+            .tasks_ended
+            .lock()
+            .entry((name.to_string(), outcome))
+            .or_default() += 1;
+    }
+
+    pub fn attacked(&self) {
+        self.attacks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn chat_sent(&self) {
+        self.chat_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// How long a goto took from starting to arriving.
+    pub fn pathfinding_took(&self, duration: Duration) {
+        self.pathfinding.observe(duration, &PATHFINDING_BUCKETS);
+    }
+
+    /// How long handling one tick of one bot took.
+    pub fn tick_took(&self, duration: Duration) {
+        self.ticks.observe(duration, &TICK_BUCKETS);
+    }
+
+    /// Everything in the Prometheus text format. The gauges are read from the
+    /// swarm as it is now.
+    pub fn render(&self, swarm: &Swarm, state: &SwarmState) -> String {
+        let mut out = String::new();
+        let bots = swarm.clone().into_iter().collect::<Vec<Client>>();
+
+        header(
+            &mut out,
+            "rustbot_bots_connected",
+            "gauge",
+            "Bots in the swarm",
+        );
+        let _ = writeln!(out, "rustbot_bots_connected {}", bots.len());
+
+        header(
+            &mut out,
+            "rustbot_reconnects_total",
+            "counter",
+            "Times each bot rejoined",
+        );
+        for (bot, count) in self.reconnects.lock().iter() {
+            let _ = writeln!(out, "rustbot_reconnects_total{{bot=\"{}\"}} {}", bot, count);
+        }
+
+        let mut queued = HashMap::<String, u64>::new();
+        for task in state.tasks.lock().iter() {
+            *queued.entry(task.task.get_name().to_string()).or_default() += 1;
+        }
+        header(
+            &mut out,
+            "rustbot_tasks_queued",
+            "gauge",
+            "Tasks waiting for a bot",
+        );
+        for (task, count) in &queued {
+            let _ = writeln!(out, "rustbot_tasks_queued{{task=\"{}\"}} {}", task, count);
+        }
+
+        let mut running = HashMap::<String, u64>::new();
+        for bot in &bots {
+            if let Some(bot_state) = bot.get_component::<BotState>() {
+                if let Some(task) = bot_state.task.lock().as_ref() {
+                    *running.entry(task.get_name().to_string()).or_default() += 1;
+                }
+            }
+        }
+        header(
+            &mut out,
+            "rustbot_tasks_running",
+            "gauge",
+            "Tasks bots are working on",
+        );
+        for (task, count) in &running {
+            let _ = writeln!(out, "rustbot_tasks_running{{task=\"{}\"}} {}", task, count);
+        }
+
+        header(
+            &mut out,
+            "rustbot_tasks_started_total",
+            "counter",
+            "Tasks bots picked up",
+        );
+        for (task, count) in self.tasks_started.lock().iter() {
+            let _ = writeln!(
+                out,
+                "rustbot_tasks_started_total{{task=\"{}\"}} {}",
+                task, count
+            );
+        }
+
+        header(
+            &mut out,
+            "rustbot_tasks_ended_total",
+            "counter",
+            "Tasks that ended, by outcome",
+        );
+        for ((task, outcome), count) in self.tasks_ended.lock().iter() {
+            let _ = writeln!(
+                out,
+                "rustbot_tasks_ended_total{{task=\"{}\",outcome=\"{}\"}} {}",
+                task, outcome, count
+            );
+        }
+
+        header(
+            &mut out,
+            "rustbot_killaura_attacks_total",
+            "counter",
+            "Hits made by killaura",
+        );
+        let _ = writeln!(
+            out,
+            "rustbot_killaura_attacks_total {}",
+            self.attacks.load(Ordering::Relaxed)
+        );
+
+        header(
+            &mut out,
+            "rustbot_chat_messages_sent_total",
+            "counter",
+            "Chat messages the bots sent",
+        );
+        let _ = writeln!(
+            out,
+            "rustbot_chat_messages_sent_total {}",
+            self.chat_messages.load(Ordering::Relaxed)
+        );
+
+        self.pathfinding.render(
+            &mut out,
+            "rustbot_pathfinding_seconds",
+            "How long gotos took to arrive",
+            &PATHFINDING_BUCKETS,
+        );
+        self.ticks.render(
+            &mut out,
+            "rustbot_tick_seconds",
+            "How long handling a tick took",
+            &TICK_BUCKETS,
+        );
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Cumulative counts for `N` buckets. The bucket bounds are passed in rather
+/// than stored, so it can be built with `Default`.
+struct Histogram<const N: usize> {
+    buckets: [AtomicU64; N],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl<const N: usize> Default for Histogram<N> {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<const N: usize> Histogram<N> {
+    fn observe(&self, duration: Duration, bounds: &[f64; N]) {
+        let seconds = duration.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter().zip(bounds) {
+            if seconds <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str, bounds: &[f64; N]) {
+        header(out, name, "histogram", help);
+        for (bucket, bound) in self.buckets.iter().zip(bounds) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{le=\"{}\"}} {}",
+                name,
+                bound,
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "{}_sum {}", name, sum);
+        let _ = writeln!(out, "{}_count {}", name, count);
+    }
+}
+
+/// Sends a chat message from `bot`, counting it.
+pub fn chat(bot: &Client, message: &str) {
+    bot.chat(message);
+    bot.resource::<SwarmState>().metrics.chat_sent();
+}
+
+/// Serves `/metrics` if the config has an address for it.
+pub fn serve(swarm: Swarm, state: SwarmState) {
+    let Some(address) = state.config.read().metrics.address.clone() else {
+        return;
+    };
+    let router = Router::new()
+        .route("/metrics", get(metrics))
+        .with_state((swarm, state));
+
+    tokio::spawn(async move {
+        let listener = match tokio::net::TcpListener::bind(&address).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                error!("Failed to serve metrics on {}: {}", address, err);
+                return;
+            }
+        };
+        info!("Serving metrics on {}", address);
+        if let Err(err) = axum::serve(listener, router).await {
+            error!("Metrics endpoint stopped: {}", err);
+        }
+    });
+}
+
+async fn metrics(State((swarm, state)): State<(Swarm, SwarmState)>) -> String {
+    state.metrics.render(&swarm, &state)
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+
+use azalea::chat::ChatPacket;
+use serde::{Deserialize, Deserializer};
+
+/// What a player may do with the swarm. Each role's commands are listed in
+/// [`Permissions::roles`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Operator,
+    Viewer,
+}
+
+/// Who may command the swarm, the `[permissions]` table of the config:
+///
+/// ```toml
+/// [permissions]
+/// whisper_only = true
+///
+/// [permissions.owners]
+/// Astatin3 = "admin"
+/// builder = "operator"
+///
+/// [permissions.roles]
+/// viewer = ["status"]
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Permissions {
+    /// Username to role.
+    pub owners: HashMap<String, Role>,
+    /// Role of players that aren't in `owners`. Without one they're denied.
+    pub default_role: Option<Role>,
+    /// Commands each role may run, `*` allowing every command. A role listed
+    /// in the config replaces its default list, the others keep theirs.
+    #[serde(deserialize_with = "over_default_roles")]
+    pub roles: HashMap<Role, Vec<String>>,
+    /// Ignore commands typed in public chat.
+    pub whisper_only: bool,
+    /// Reply to players that aren't allowed to run a command. Empty to ignore
+    /// them silently.
+    pub denial_message: String,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        let operator = [
+            "status", "chat", "goto", "fetch", "delay", "wait", "seq", "par", "race", "repeat",
+            "cancel", "remove", "help",
+        ];
+        Self {
+            owners: HashMap::new(),
+            default_role: None,
+            roles: HashMap::from([
+                (Role::Admin, vec!["*".to_string()]),
+                (Role::Operator, operator.map(|s| s.to_string()).to_vec()),
+                (Role::Viewer, vec!["status".to_string(), "help".to_string()]),
+            ]),
+            whisper_only: false,
+            denial_message: "You aren't allowed to do that".to_string(),
+        }
+    }
+}
+
+fn over_default_roles<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<Role, Vec<String>>, D::Error> {
+    let mut roles = Permissions::default().roles;
+    roles.extend(HashMap::<Role, Vec<String>>::deserialize(deserializer)?);
+    Ok(roles)
+}
+
+/// Why a command was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Denial {
+    /// Not addressed to the swarm at all, e.g. public chat in whisper-only mode.
+    Ignored,
+    /// The sender isn't allowed to run the command.
+    Denied,
+}
+
+impl Permissions {
+    pub fn role_of(&self, username: &str) -> Option<Role> {
+        self.owners.get(username).copied().or(self.default_role)
+    }
+
+    pub fn allows(&self, role: Role, command: &str) -> bool {
+        self.roles
+            .get(&role)
+            .is_some_and(|commands| commands.iter().any(|c| c == "*" || c == command))
+    }
+
+    /// Checks whether the sender of `chat` may run `command`, before anything
+    /// gets queued.
+    pub fn check(&self, chat: &ChatPacket, command: &str) -> Result<(), Denial> {
+        if self.whisper_only && !chat.is_whisper() {
+            return Err(Denial::Ignored);
+        }
+        let Some(sender) = chat.sender() else {
+            return Err(Denial::Ignored);
+        };
+        match self.role_of(sender.as_str()) {
+            Some(role) if self.allows(role, command) => Ok(()),
+            _ => Err(Denial::Denied),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Config {
+        permissions: Permissions,
+    }
+
+    // The example from the docs above
+    #[test]
+    fn configured_roles_keep_the_other_defaults() {
+        let Config { permissions } = toml::from_str(
+            r#"
+            [permissions]
+            whisper_only = true
+
+            [permissions.owners]
+            Astatin3 = "admin"
+            builder = "operator"
+
+            [permissions.roles]
+            viewer = ["status"]
+            "#,
+        )
+        .unwrap();
+        assert!(permissions.allows(Role::Admin, "reload"));
+        assert!(permissions.allows(Role::Operator, "goto"));
+        assert!(permissions.allows(Role::Viewer, "status"));
+        assert!(!permissions.allows(Role::Viewer, "help"));
+        assert_eq!(permissions.role_of("Astatin3"), Some(Role::Admin));
+        assert_eq!(permissions.role_of("builder"), Some(Role::Operator));
+    }
+}
@@ -0,0 +1,225 @@
+//! Saves the task queue and every bot's running task to disk, so a crash or
+//! redeploy picks the work plan back up where it left off.
+
+use std::{collections::HashMap, fs, path::Path, time::Duration};
+
+use azalea::{BlockPos, swarm::Swarm};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    BotState, SwarmState,
+    bot_task::{
+        Chat, Delay, GotoBlock, Parallel, Repeat, Sequence, WaitFor, destination::Destination,
+        fetch,
+    },
+    command_controler::{BotTask, QueuedTask, RESUME_PRIORITY, TaskLimits, TaskTarget},
+};
+
+/// How often autosave checks whether it was turned on while it's off.
+static DISABLED_RECHECK: Duration = Duration::from_secs(5);
+
+/// A task and its progress, as written to the state file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TaskSnapshot {
+    Chat {
+        messages: Vec<String>,
+        /// The next message to send.
+        index: usize,
+    },
+    Goto {
+        x: i32,
+        y: Option<i32>,
+        z: i32,
+    },
+    /// A goto that hadn't been resolved to a block yet.
+    GotoDestination {
+        destination: Destination,
+    },
+    Delay {
+        remaining_ms: u64,
+    },
+    /// Starts over, async tasks can't be saved partway through.
+    Fetch {
+        x: i32,
+        y: i32,
+        z: i32,
+    },
+    /// The steps that haven't finished yet, the first one with its progress.
+    Sequence {
+        steps: Vec<TaskSnapshot>,
+    },
+    /// The children that haven't finished yet.
+    Parallel {
+        children: Vec<TaskSnapshot>,
+        wait_for: WaitFor,
+    },
+    Repeat {
+        template: Box<TaskSnapshot>,
+        times: Option<u32>,
+        iteration: u32,
+        /// The run that was in progress.
+        current: Option<Box<TaskSnapshot>>,
+    },
+}
+
+impl TaskSnapshot {
+    pub fn restore(self) -> Box<dyn BotTask> {
+        match self {
+            TaskSnapshot::Chat { messages, index } => Box::new(Chat::resume(messages, index)),
+            TaskSnapshot::Goto { x, y, z } => Box::new(GotoBlock::new(x, y, z)),
+            TaskSnapshot::GotoDestination { destination } => Box::new(GotoBlock::to(destination)),
+            TaskSnapshot::Delay { remaining_ms } => {
+                Box::new(Delay::new(Duration::from_millis(remaining_ms)))
+            }
+            TaskSnapshot::Fetch { x, y, z } => Box::new(fetch(BlockPos { x, y, z })),
+            TaskSnapshot::Sequence { steps } => Box::new(Sequence::new(
+                steps.into_iter().map(TaskSnapshot::restore).collect(),
+            )),
+            TaskSnapshot::Parallel { children, wait_for } => Box::new(Parallel::new(
+                children.into_iter().map(TaskSnapshot::restore).collect(),
+                wait_for,
+            )),
+            TaskSnapshot::Repeat {
+                template,
+                times,
+                iteration,
+                current,
+            } => Box::new(Repeat::resume(
+                template.restore(),
+                times,
+                iteration,
+                current.map(|current| current.restore()),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedTaskSnapshot {
+    pub task: TaskSnapshot,
+    pub limits: TaskLimits,
+    pub priority: i32,
+    pub target: TaskTarget,
+    pub taken_by: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SwarmSnapshot {
+    pub queue: Vec<QueuedTaskSnapshot>,
+    /// What each bot was working on, by username.
+    pub running: HashMap<String, QueuedTaskSnapshot>,
+}
+
+impl SwarmSnapshot {
+    pub fn capture(swarm: &Swarm, state: &SwarmState) -> Self {
+        let queue = state
+            .tasks
+            .lock()
+            .iter()
+            .map(QueuedTask::snapshot)
+            .collect();
+        let mut running = HashMap::new();
+        for bot in swarm.clone() {
+            let Some(bot_state) = bot.get_component::<BotState>() else {
+                continue;
+            };
+            if let Some(task) = bot_state.task.lock().as_ref() {
+                let username = bot.username();
+                running.insert(
+                    username.clone(),
+                    QueuedTaskSnapshot {
+                        task: task.snapshot(),
+                        limits: task.limits(),
+                        priority: RESUME_PRIORITY,
+                        target: TaskTarget::Bot(username),
+                        taken_by: Vec::new(),
+                    },
+                );
+            }
+        }
+        Self { queue, running }
+    }
+
+    /// The queue to start with, interrupted tasks first so each bot resumes
+    /// what it was doing.
+    pub fn restore(self) -> Vec<QueuedTask> {
+        self.running
+            .into_values()
+            .chain(self.queue)
+            .map(QueuedTask::restore)
+            .collect()
+    }
+}
+
+/// Writes the snapshot next to the state file first, so a crash mid-write
+/// never leaves a truncated file behind.
+pub fn save(path: &Path, snapshot: &SwarmSnapshot) -> anyhow::Result<()> {
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, serde_json::to_string_pretty(snapshot)?)?;
+    fs::rename(temp, path)?;
+    Ok(())
+}
+
+/// The saved tasks, or none if there's no state file. One that can't be read,
+/// like a corrupt one or one from an older version, is moved aside rather
+/// than keeping the swarm from starting.
+pub fn load(path: &Path) -> Vec<QueuedTask> {
+    if !path.exists() {
+        return Vec::new();
+    }
+    let snapshot = fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|json| Ok(serde_json::from_str::<SwarmSnapshot>(json.as_str())?));
+    let snapshot = match snapshot {
+        Ok(snapshot) => snapshot,
+        Err(err) => {
+            let mut aside = path.as_os_str().to_owned();
+            aside.push(".bad");
+            error!(
+                "Failed to read the tasks in {}, starting without them and moving it to {}: {}",
+                path.display(),
+                Path::new(&aside).display(),
+                err
+            );
+            if let Err(err) = fs::rename(path, &aside) {
+                error!("Failed to move {} aside: {}", path.display(), err);
+            }
+            return Vec::new();
+        }
+    };
+    info!(
+        "Restoring {} queued and {} interrupted tasks from {}",
+        snapshot.queue.len(),
+        snapshot.running.len(),
+        path.display()
+    );
+    snapshot.restore()
+}
+
+/// Saves the swarm's tasks every `interval_secs` from the config.
+pub fn autosave(swarm: Swarm, state: SwarmState) {
+    tokio::spawn(async move {
+        loop {
+            let (path, interval) = {
+                let config = state.config.read();
+                (
+                    config.persistence.path.clone(),
+                    config.persistence.interval(),
+                )
+            };
+            // a reload may turn it on later
+            let Some(interval) = interval else {
+                tokio::time::sleep(DISABLED_RECHECK).await;
+                continue;
+            };
+            tokio::time::sleep(interval).await;
+
+            let snapshot = SwarmSnapshot::capture(&swarm, &state);
+            if let Err(err) = save(&path, &snapshot) {
+                error!("Failed to save tasks to {}: {}", path.display(), err);
+            }
+        }
+    });
+}
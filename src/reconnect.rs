@@ -0,0 +1,326 @@
+//! Rejoins bots that got disconnected, backing off between attempts and giving
+//! up on the ones the server won't let back in. What happens depends on the
+//! [`KickReason`] the kick message was classified as, see
+//! [`ReconnectConfig`](crate::config::ReconnectConfig).
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use azalea::{Account, JoinOpts, prelude::*, swarm::Swarm};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::{
+    BotState, SwarmState, chat,
+    command_controler::{TaskTarget, insert_task},
+    config::InterruptedTask,
+    kick::KickReason,
+    metrics,
+};
+
+/// How long to wait for the bot's own `Event::Disconnect` to say why it was
+/// kicked before reconnecting without knowing.
+static KICK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Reconnect attempts in a row and the last kick, by username.
+#[derive(Clone, Default)]
+pub struct Reconnects {
+    bots: Arc<Mutex<HashMap<String, Attempts>>>,
+}
+
+struct Attempts {
+    count: u32,
+    kick: KickReason,
+    reason: Option<String>,
+    connected: bool,
+    gave_up: bool,
+    /// Notified once the kick is recorded. The swarm and the bot handler
+    /// both hear about a disconnect, in no particular order, and only the
+    /// bot's side has the message.
+    kicked: Arc<Notify>,
+}
+
+impl Default for Attempts {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            kick: KickReason::Other,
+            reason: None,
+            connected: true,
+            gave_up: false,
+            kicked: Arc::new(Notify::new()),
+        }
+    }
+}
+
+/// A bot that's out of the swarm, waiting to reconnect or given up on.
+#[derive(Debug, Clone, Serialize)]
+pub struct DisconnectedBot {
+    pub username: String,
+    pub kick: KickReason,
+    pub reason: Option<String>,
+    pub attempts: u32,
+    pub gave_up: bool,
+}
+
+impl Reconnects {
+    /// Starts the backoff over once a bot made it back in.
+    pub fn joined(&self, username: &str) {
+        let mut bots = self.bots.lock();
+        let attempts = bots.entry(username.to_string()).or_default();
+        *attempts = Attempts::default();
+    }
+
+    /// Records the kick and lets [`reconnect`] go ahead with it.
+    pub fn disconnected(&self, username: &str, kick: KickReason, reason: Option<String>) {
+        self.record(username, kick, reason).notify_one();
+    }
+
+    /// Records a join that didn't go through, which no bot handler hears
+    /// about.
+    pub fn failed(&self, username: &str, reason: String) {
+        self.record(
+            username,
+            KickReason::classify(Some(reason.as_str())),
+            Some(reason),
+        );
+    }
+
+    fn record(&self, username: &str, kick: KickReason, reason: Option<String>) -> Arc<Notify> {
+        let mut bots = self.bots.lock();
+        let attempts = bots.entry(username.to_string()).or_default();
+        attempts.kick = kick;
+        attempts.reason = reason;
+        attempts.connected = false;
+        attempts.kicked.clone()
+    }
+
+    /// Waits for the bot handler to record why the bot was disconnected.
+    async fn kicked(&self, username: &str) {
+        let kicked = self
+            .bots
+            .lock()
+            .entry(username.to_string())
+            .or_default()
+            .kicked
+            .clone();
+        if tokio::time::timeout(KICK_TIMEOUT, kicked.notified())
+            .await
+            .is_err()
+        {
+            warn!(bot = username, "Never heard why the bot was disconnected");
+            self.record(username, KickReason::Other, None);
+        }
+    }
+
+    /// Why the bot was last disconnected.
+    pub fn reason(&self, username: &str) -> Option<(KickReason, Option<String>)> {
+        let bots = self.bots.lock();
+        let attempts = bots.get(username)?;
+        Some((attempts.kick, attempts.reason.clone()))
+    }
+
+    /// The bots that aren't in the swarm right now, by username.
+    pub fn disconnected_bots(&self) -> Vec<DisconnectedBot> {
+        let mut bots = self
+            .bots
+            .lock()
+            .iter()
+            .filter(|(_, attempts)| !attempts.connected)
+            .map(|(username, attempts)| DisconnectedBot {
+                username: username.clone(),
+                kick: attempts.kick,
+                reason: attempts.reason.clone(),
+                attempts: attempts.count,
+                gave_up: attempts.gave_up,
+            })
+            .collect::<Vec<DisconnectedBot>>();
+        bots.sort_by(|a, b| a.username.cmp(&b.username));
+        bots
+    }
+
+    /// Forgets a bot, like one that was removed from the config.
+    pub fn remove(&self, username: &str) {
+        self.bots.lock().remove(username);
+    }
+
+    fn give_up(&self, username: &str) {
+        if let Some(attempts) = self.bots.lock().get_mut(username) {
+            attempts.gave_up = true;
+        }
+    }
+
+    fn next_attempt(&self, username: &str) -> u32 {
+        let mut bots = self.bots.lock();
+        let attempts = bots.entry(username.to_string()).or_default();
+        attempts.count += 1;
+        attempts.count
+    }
+}
+
+/// Records why the bot was disconnected and deals with the task it was
+/// running, while its state is still around.
+pub fn interrupted(
+    bot: &Client,
+    state: &BotState,
+    swarm_state: &SwarmState,
+    kick: KickReason,
+    reason: Option<String>,
+) {
+    let username = bot.username();
+    swarm_state
+        .reconnects
+        .disconnected(username.as_str(), kick, reason);
+
+    let Some(mut task) = state.task.lock().take() else {
+        return;
+    };
+    // stops it before a copy is requeued, so the two never run at once
+    task.cancel(bot);
+    let interrupted_task = swarm_state.config.read().reconnect.interrupted_task;
+    let target = match interrupted_task {
+        InterruptedTask::Resume => TaskTarget::Bot(username),
+        InterruptedTask::Requeue => TaskTarget::Any,
+        InterruptedTask::Drop => {
+            info!(task = %task.describe(), "Dropping the interrupted task");
+            return;
+        }
+    };
+    info!(task = %task.describe(), ?target, "Requeuing the interrupted task");
+    insert_task(&mut swarm_state.tasks.lock(), task.requeue(target));
+}
+
+/// Rejoins a bot once the bot handler has said why it was disconnected. Waiting
+/// for that first also means a bot removed from the config is only forgotten
+/// after its handler recorded the kick, so it doesn't linger as disconnected.
+pub async fn reconnect(swarm: &Swarm, state: &SwarmState, account: &Account, join_opts: &JoinOpts) {
+    state.reconnects.kicked(account.username.as_str()).await;
+    rejoin(swarm, state, account, join_opts).await;
+}
+
+/// Waits out the backoff and adds the bot back to the swarm, unless the
+/// policy gives up on it or it was removed from the config. A join that fails
+/// counts as another attempt.
+pub async fn rejoin(swarm: &Swarm, state: &SwarmState, account: &Account, join_opts: &JoinOpts) {
+    let username = account.username.as_str();
+    loop {
+        if removed(state, username) {
+            return;
+        }
+        let attempt = state.reconnects.next_attempt(username);
+        let (kick, reason) = state
+            .reconnects
+            .reason(username)
+            .unwrap_or((KickReason::Other, None));
+        let (delay, alert) = {
+            let config = state.config.read();
+            (
+                config.reconnect.delay(attempt, kick, reason.as_deref()),
+                // only once per kick, not on every failed attempt after it
+                attempt == 1 && config.reconnect.alert.contains(&kick),
+            )
+        };
+        if alert {
+            alert_owners(swarm, state, username, kick, reason.as_deref());
+        }
+
+        let Some(delay) = delay else {
+            warn!(
+                bot = username,
+                %kick,
+                ?reason,
+                attempt,
+                "Giving up on reconnecting"
+            );
+            state.reconnects.give_up(username);
+            release_tasks(state, username);
+            return;
+        };
+        info!(bot = username, %kick, ?reason, attempt, ?delay, "Reconnecting");
+        tokio::time::sleep(delay).await;
+        // the config may have been reloaded in the meantime
+        if removed(state, username) {
+            return;
+        }
+        match swarm
+            .add_with_opts(account, BotState::default(), join_opts)
+            .await
+        {
+            Ok(_) => {
+                state.metrics.reconnected(username);
+                return;
+            }
+            Err(err) => {
+                warn!(bot = username, attempt, "Failed to rejoin: {}", err);
+                state.reconnects.failed(username, err.to_string());
+            }
+        }
+    }
+}
+
+/// Whispers the owners through another bot that's still in the swarm, if
+/// there is one.
+fn alert_owners(
+    swarm: &Swarm,
+    state: &SwarmState,
+    username: &str,
+    kick: KickReason,
+    reason: Option<&str>,
+) {
+    let Some(messenger) = swarm
+        .clone()
+        .into_iter()
+        .find(|bot| bot.username() != username)
+    else {
+        warn!(bot = username, %kick, "No bot left to alert the owners");
+        return;
+    };
+    let owners = state
+        .config
+        .read()
+        .permissions
+        .owners
+        .keys()
+        .cloned()
+        .collect::<Vec<String>>();
+    // kick screens are often several lines long and full of formatting, which
+    // would get the messenger kicked too
+    let reason = reason.map_or("no reason given".to_string(), chat::sanitize);
+    for owner in owners {
+        let alert = format!(
+            "/w {} {} was kicked ({}): {}",
+            owner, username, kick, reason
+        );
+        metrics::chat(&messenger, chat::truncate(alert.as_str()).as_str());
+    }
+}
+
+/// Forgets a bot that's no longer in the config and lets any bot pick up the
+/// tasks that were waiting for it. Returns whether it was removed.
+fn removed(state: &SwarmState, username: &str) -> bool {
+    let configured = state
+        .config
+        .read()
+        .accounts
+        .usernames()
+        .iter()
+        .any(|configured| configured == username);
+    if configured {
+        return false;
+    }
+    info!(bot = username, "Removed from the config, not reconnecting");
+    state.reconnects.remove(username);
+    release_tasks(state, username);
+    true
+}
+
+/// Lets any bot pick up the tasks that were waiting for one that won't be
+/// back.
+fn release_tasks(state: &SwarmState, username: &str) {
+    for task in state.tasks.lock().iter_mut() {
+        if task.target == TaskTarget::Bot(username.to_string()) {
+            task.target = TaskTarget::Any;
+        }
+    }
+}
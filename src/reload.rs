@@ -0,0 +1,89 @@
+//! Applies config changes to a running swarm, either when the config file
+//! changes on disk or when an owner runs `!reload`.
+
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use azalea::{ClientInformation, swarm::Swarm};
+use tracing::{error, info};
+
+use crate::{BotState, SwarmState, config};
+
+/// How often the config file is checked for changes.
+static WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Reloads the config whenever the file's modification time changes.
+pub fn watch(swarm: Swarm, state: SwarmState) {
+    tokio::spawn(async move {
+        let path = state.config.read().path().to_path_buf();
+        let mut last_modified = modified(&path);
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            interval.tick().await;
+            let modified = modified(&path);
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+
+            info!("{} changed, reloading", path.display());
+            if let Err(err) = reload(&swarm, &state).await {
+                error!("Failed to reload the config: {}", err);
+            }
+        }
+    });
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Swaps in the config from disk. Owners, permissions, task limits, killaura
+/// and the chat delay are read from it as they're used, so only the accounts
+/// and view distances need to be applied here.
+pub async fn reload(swarm: &Swarm, state: &SwarmState) -> anyhow::Result<()> {
+    let config = state.config.read().reload()?;
+    config.check();
+    let old_accounts = state.config.read().accounts.usernames();
+    let new_accounts = config.accounts.usernames();
+    *state.config.write() = config.clone();
+
+    for username in old_accounts
+        .iter()
+        .filter(|username| !new_accounts.contains(username))
+    {
+        state.joins.remove(username);
+    }
+
+    for bot in swarm.clone() {
+        let username = bot.username();
+        if !new_accounts.contains(&username) {
+            info!(bot = %username, "Removed from the config, disconnecting");
+            if let Some(bot_state) = bot.get_component::<BotState>() {
+                bot_state.cancel_task(&bot);
+            }
+            bot.disconnect();
+            continue;
+        }
+        bot.set_client_information(ClientInformation {
+            view_distance: config.bot_settings(username.as_str()).view_distance,
+            ..Default::default()
+        })
+        .await;
+    }
+
+    for username in new_accounts
+        .iter()
+        .filter(|username| !old_accounts.contains(username))
+    {
+        info!(bot = %username, "Added to the config, waiting to join");
+        state.joins.push(config::account(username.as_str()).await?);
+    }
+
+    Ok(())
+}
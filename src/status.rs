@@ -0,0 +1,307 @@
+//! What every bot is doing and how it's faring, for `!status` and anything
+//! else that reports on the swarm.
+
+use std::time::Duration;
+
+use azalea::{
+    entity::metadata::Health, local_player::Hunger, prelude::*, swarm::Swarm, world::InstanceName,
+};
+use serde::Serialize;
+
+use crate::{
+    BotState, SwarmState,
+    command_controler::{TaskProgress, TaskTarget},
+    reconnect::DisconnectedBot,
+};
+
+/// How many bots or queued tasks are listed per chat page.
+static PAGE_SIZE: usize = 4;
+/// How many of the bots waiting to join or reconnect are named on a page.
+static FEW: usize = 4;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BotStatus {
+    pub username: String,
+    pub position: [f64; 3],
+    pub dimension: Option<String>,
+    pub health: Option<f32>,
+    pub food: Option<u32>,
+    /// The running task's name, `None` while idle.
+    pub task: Option<String>,
+    pub progress: Option<TaskProgress>,
+    /// Latency from the tab list, in milliseconds.
+    pub ping: Option<i32>,
+    /// Seconds since the bot joined.
+    pub uptime: u64,
+}
+
+impl BotStatus {
+    pub fn of(bot: &Client) -> Self {
+        let position = bot.position();
+        let bot_state = bot.get_component::<BotState>();
+        Self {
+            username: bot.username(),
+            position: [position.x, position.y, position.z],
+            dimension: bot
+                .get_component::<InstanceName>()
+                .map(|instance_name| instance_name.to_string()),
+            health: bot.get_component::<Health>().map(|health| *health),
+            food: bot.get_component::<Hunger>().map(|hunger| hunger.food),
+            task: bot_state.as_ref().and_then(|state| {
+                state
+                    .task
+                    .lock()
+                    .as_ref()
+                    .map(|task| task.get_name().to_string())
+            }),
+            progress: bot_state
+                .as_ref()
+                .and_then(|state| state.task.lock().as_ref()?.progress()),
+            ping: bot
+                .tab_list()
+                .get(&bot.uuid())
+                .map(|player_info| player_info.latency),
+            uptime: bot_state
+                .map(|state| state.joined_at.elapsed())
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+
+    /// The task and its progress, e.g. `Chat: 3/7 messages (43%)`.
+    pub fn describe_task(&self) -> String {
+        match (&self.task, &self.progress) {
+            (Some(task), Some(progress)) => format!("{}: {}", task, progress),
+            (Some(task), None) => task.clone(),
+            (None, _) => "idle".to_string(),
+        }
+    }
+
+    /// One line for chat, e.g. `bot0 at 12 64 -3 in minecraft:overworld, 20hp
+    /// 18 food, Goto, 42ms, up 5m3s`.
+    pub fn summary(&self) -> String {
+        let [x, y, z] = self.position;
+        format!(
+            "{} at {:.0} {:.0} {:.0} in {}, {}hp {} food, {}, {}, up {}",
+            self.username,
+            x,
+            y,
+            z,
+            self.dimension.as_deref().unwrap_or("?"),
+            self.health
+                .map_or("?".to_string(), |health| format!("{:.0}", health)),
+            self.food.map_or("?".to_string(), |food| food.to_string()),
+            self.describe_task(),
+            self.ping
+                .map_or("?".to_string(), |ping| format!("{}ms", ping)),
+            format_duration(Duration::from_secs(self.uptime)),
+        )
+    }
+}
+
+/// A task waiting for a bot.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedStatus {
+    /// What `!remove` takes to drop it.
+    pub index: usize,
+    pub task: String,
+    pub priority: i32,
+    pub target: TaskTarget,
+}
+
+impl QueuedStatus {
+    /// e.g. `#2 Goto, priority 5, to=bot1`.
+    pub fn summary(&self) -> String {
+        format!(
+            "#{} {}, priority {}, to={}",
+            self.index, self.task, self.priority, self.target
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SwarmStatus {
+    pub bots: Vec<BotStatus>,
+    /// Tasks waiting for a bot, in the order they're handed out.
+    pub queued: Vec<QueuedStatus>,
+    /// Bots that haven't had their turn to join yet, in order.
+    pub waiting: Vec<String>,
+    /// Bots that were kicked and aren't back yet.
+    pub disconnected: Vec<DisconnectedBot>,
+}
+
+impl SwarmStatus {
+    pub fn of(swarm: &Swarm, state: &SwarmState) -> Self {
+        Self {
+            bots: swarm
+                .clone()
+                .into_iter()
+                .map(|bot| BotStatus::of(&bot))
+                .collect(),
+            queued: state
+                .tasks
+                .lock()
+                .iter()
+                .enumerate()
+                .map(|(index, task)| QueuedStatus {
+                    index,
+                    task: task.task.get_name().to_string(),
+                    priority: task.priority,
+                    target: task.target.clone(),
+                })
+                .collect(),
+            waiting: state.joins.usernames(),
+            disconnected: state.reconnects.disconnected_bots(),
+        }
+    }
+
+    /// The bots come first, then the queued tasks after them.
+    pub fn pages(&self) -> usize {
+        (self.bots.len() + self.queued.len())
+            .div_ceil(PAGE_SIZE)
+            .max(1)
+    }
+
+    /// The chat lines for a page, counting from 1.
+    pub fn page(&self, page: usize) -> Vec<String> {
+        let mut lines = vec![format!(
+            "{} bots, {} queued tasks (page {}/{})",
+            self.bots.len(),
+            self.queued.len(),
+            page,
+            self.pages()
+        )];
+        lines.extend(
+            self.bots
+                .iter()
+                .map(BotStatus::summary)
+                .chain(self.queued.iter().map(QueuedStatus::summary))
+                .skip((page - 1) * PAGE_SIZE)
+                .take(PAGE_SIZE),
+        );
+        // cut down to a few names so they fit on every page, the table has
+        // all of them
+        if !self.waiting.is_empty() {
+            lines.push(format!(
+                "Waiting to join ({}): {}",
+                self.waiting.len(),
+                first_few(self.waiting.iter().cloned())
+            ));
+        }
+        if !self.disconnected.is_empty() {
+            lines.push(format!(
+                "Disconnected ({}): {}",
+                self.disconnected.len(),
+                first_few(self.disconnected.iter().map(DisconnectedBot::summary))
+            ));
+        }
+        lines
+    }
+
+    /// A table with a row per bot, for the console.
+    pub fn table(&self) -> String {
+        let mut table = format!(
+            "{:<16} {:>24} {:<20} {:>6} {:>4} {:<12} {:>6} {:>8} {}\n",
+            "USERNAME",
+            "POSITION",
+            "DIMENSION",
+            "HEALTH",
+            "FOOD",
+            "TASK",
+            "PING",
+            "UPTIME",
+            "PROGRESS"
+        );
+        for bot in &self.bots {
+            let [x, y, z] = bot.position;
+            table.push_str(
+                format!(
+                    "{:<16} {:>24} {:<20} {:>6} {:>4} {:<12} {:>6} {:>8} {}\n",
+                    bot.username,
+                    format!("{:.1} {:.1} {:.1}", x, y, z),
+                    bot.dimension.as_deref().unwrap_or("-"),
+                    bot.health
+                        .map_or("-".to_string(), |health| format!("{:.1}", health)),
+                    bot.food.map_or("-".to_string(), |food| food.to_string()),
+                    bot.task.as_deref().unwrap_or("-"),
+                    bot.ping
+                        .map_or("-".to_string(), |ping| format!("{}ms", ping)),
+                    format_duration(Duration::from_secs(bot.uptime)),
+                    bot.progress
+                        .as_ref()
+                        .map_or("-".to_string(), |progress| progress.to_string()),
+                )
+                .as_str(),
+            );
+        }
+        for username in &self.waiting {
+            table.push_str(format!("{:<16} waiting to join\n", username).as_str());
+        }
+        for bot in &self.disconnected {
+            table.push_str(
+                format!(
+                    "{:<16} {} after {} attempts{}: {}\n",
+                    bot.username,
+                    bot.kick,
+                    bot.attempts,
+                    if bot.gave_up { ", gave up" } else { "" },
+                    bot.reason.as_deref().unwrap_or("-"),
+                )
+                .as_str(),
+            );
+        }
+        table.push_str(
+            format!(
+                "\n{:>5} {:<12} {:>8} {}\n",
+                "INDEX", "TASK", "PRIORITY", "TO"
+            )
+            .as_str(),
+        );
+        for task in &self.queued {
+            table.push_str(
+                format!(
+                    "{:>5} {:<12} {:>8} {}\n",
+                    task.index, task.task, task.priority, task.target
+                )
+                .as_str(),
+            );
+        }
+        table.push_str(format!("{} queued tasks", self.queued.len()).as_str());
+        table
+    }
+}
+
+impl DisconnectedBot {
+    /// e.g. `bot2 (banned, gave up)` or `bot3 (server restart, attempt 2)`.
+    pub fn summary(&self) -> String {
+        if self.gave_up {
+            format!("{} ({}, gave up)", self.username, self.kick)
+        } else {
+            format!(
+                "{} ({}, attempt {})",
+                self.username, self.kick, self.attempts
+            )
+        }
+    }
+}
+
+/// e.g. `bot0, bot1, bot2 and 12 more`.
+fn first_few(names: impl ExactSizeIterator<Item = String>) -> String {
+    let more = names.len().saturating_sub(FEW);
+    let names = names.take(FEW).collect::<Vec<String>>().join(", ");
+    if more == 0 {
+        names
+    } else {
+        format!("{} and {} more", names, more)
+    }
+}
+
+/// `1h2m`, `5m3s` or `12s`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m{}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
@@ -28,6 +28,7 @@ pub mod killaura;
 use std::time::Duration;
 use std::{env, process};
 use std::{sync::Arc, thread};
+use tracing::{debug, error, info, warn};
 
 use azalea::ClientInformation;
 use azalea::brigadier::command_dispatcher::CommandDispatcher;
@@ -87,12 +88,12 @@ fn deadlock_detection_thread() {
             continue;
         }
 
-        println!("{} deadlocks detected", deadlocks.len());
+        error!("{} deadlocks detected", deadlocks.len());
         for (i, threads) in deadlocks.iter().enumerate() {
-            println!("Deadlock #{i}");
+            error!("Deadlock #{i}");
             for t in threads {
-                println!("Thread Id {:#?}", t.thread_id());
-                println!("{:#?}", t.backtrace());
+                error!("Thread Id {:#?}", t.thread_id());
+                error!("{:#?}", t.backtrace());
             }
         }
     }
@@ -150,7 +151,7 @@ async fn handle(bot: Client, event: azalea::Event, state: State) -> anyhow::Resu
                 return Ok(());
             }
 
-            println!("{:?}", chat.message());
+            debug!("{:?}", chat.message());
 
             let command = if chat.is_whisper() {
                 Some(content)
@@ -168,7 +169,7 @@ async fn handle(bot: Client, event: azalea::Event, state: State) -> anyhow::Resu
                 ) {
                     Ok(_) => {}
                     Err(err) => {
-                        eprintln!("{err:?}");
+                        warn!("{err:?}");
                         let command_source = CommandSource {
                             bot,
                             chat: chat.clone(),
@@ -195,13 +196,13 @@ async fn handle(bot: Client, event: azalea::Event, state: State) -> anyhow::Resu
 async fn swarm_handle(_swarm: Swarm, event: SwarmEvent, _state: SwarmState) -> anyhow::Result<()> {
     match &event {
         SwarmEvent::Disconnect(account, _join_opts) => {
-            println!("bot got kicked! {}", account.username);
+            warn!(bot = %account.username, "Got kicked");
         }
         SwarmEvent::Chat(chat) => {
             if chat.message().to_string() == "The particle was not visible for anybody" {
                 return Ok(());
             }
-            println!("{}", chat.message().to_ansi());
+            info!("{}", chat.message());
         }
         _ => {}
     }
@@ -0,0 +1,69 @@
+//! The ECS side of running tasks. Tasks aren't systems themselves: they drive
+//! their bot through a [`Client`], whose methods lock the world and so can't
+//! be called from inside a system. They stay in the bot's state and are
+//! advanced from the bot handler on `Event::Tick`, which azalea sends once per
+//! `GameTick`, so they still run once per tick instead of on every packet.
+//! What can be worked out from components alone, like whether the bot is
+//! stuck, is tracked here by a system, and the runner reads it back with one
+//! `get_component` a tick.
+//!
+//! [`Client`]: azalea::Client
+
+use azalea::{
+    Vec3,
+    app::{App, Plugin},
+    core::tick::GameTick,
+    ecs::prelude::*,
+    entity::{LocalEntity, Position},
+    pathfinder::{ExecutingPath, Pathfinder},
+};
+
+/// How far the bot has to move in a tick to count as moving.
+static STALL_EPSILON: f64 = 0.05;
+
+pub struct TaskPlugin;
+
+impl Plugin for TaskPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(GameTick, (add_motion, track_motion).chain());
+    }
+}
+
+/// How long a bot has been standing still.
+#[derive(Component, Clone, Debug, Default)]
+pub struct Motion {
+    last_position: Option<Vec3>,
+    /// Game ticks in a row the bot hasn't moved while the pathfinder was
+    /// neither calculating nor executing a path.
+    pub still_ticks: u32,
+}
+
+fn add_motion(mut commands: Commands, query: Query<Entity, (With<LocalEntity>, Without<Motion>)>) {
+    for entity in &query {
+        commands.entity(entity).insert(Motion::default());
+    }
+}
+
+fn track_motion(
+    mut query: Query<(
+        &mut Motion,
+        &Position,
+        Option<&Pathfinder>,
+        Option<&ExecutingPath>,
+    )>,
+) {
+    for (mut motion, position, pathfinder, executing_path) in &mut query {
+        let position = **position;
+        let moved = motion
+            .last_position
+            .is_none_or(|last| last.distance_to(&position) > STALL_EPSILON);
+        let pathfinding = pathfinder.is_some_and(|pathfinder| pathfinder.is_calculating)
+            || executing_path.is_some();
+        motion.last_position = Some(position);
+        if moved || pathfinding {
+            motion.still_ticks = 0;
+        } else {
+            motion.still_ticks += 1;
+        }
+    }
+}